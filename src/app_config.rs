@@ -1,35 +1,927 @@
-use notify_rust::Notification;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Datelike, Local, NaiveDateTime, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use notify_rust::{Notification, Timeout, Urgency};
+use secret_service::blocking::SecretService;
+use secret_service::EncryptionType;
+use serde::Deserialize;
 
+pub const DEFAULT_API_URL: &str = "https://api.github.com";
+pub const DEFAULT_ACCOUNT_NAME: &str = "default";
+pub const CONFIG_DIR_NAME: &str = ".config/github-notifier";
+pub const CONFIG_FILE_NAME: &str = "config.toml";
+pub const DEFAULT_ASSETS_DIR: &str = "./assets";
+pub const TOKENS_DIR_NAME: &str = "tokens";
+const SECRET_SERVICE_APPLICATION: &str = "github-notifier";
+
+/// Every `reason` the notifications API can report
+pub const ALL_REASONS: [&str; 13] = [
+    "assign", "author", "ci_activity", "comment", "manual", "mention",
+    "push", "review_requested", "security_alert", "state_change",
+    "subscribed", "team_mention", "your_activity",
+];
+
+/// Default popup order when several are waiting, reasons not listed come last
+pub const DEFAULT_PRIORITY: [&str; 6] = [
+    "review_requested", "mention", "assign", "team_mention", "security_alert", "author",
+];
+
+/// Reactions the API accepts on comments
+pub const ALL_REACTIONS: [&str; 8] = ["+1", "-1", "laugh", "confused", "heart", "hooray", "rocket", "eyes"];
+
+/// A single GitHub account polled by the notifier
+#[derive(Clone)]
+pub struct AccountConfig {
+    /// Short label used to tag popups, tray entries and snooze rows, e.g. `personal` or `work`
+    pub name: String,
+    pub github_token: String,
+    /// Base URL of the GitHub REST API, e.g. `https://github.example.com/api/v3` for Enterprise Server
+    pub api_url: String,
+}
+
+impl AccountConfig {
+    /// Web UI matching `api_url`, `https://github.com` or the Enterprise Server host
+    pub fn web_url(&self) -> String {
+        if self.api_url.trim_end_matches('/') == DEFAULT_API_URL {
+            return String::from("https://github.com");
+        }
+        self.api_url.trim_end_matches('/').trim_end_matches("/api/v3").to_string()
+    }
+}
+
+// The token is left out on purpose so it never ends up in the log
+impl fmt::Debug for AccountConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AccountConfig")
+            .field("name", &self.name)
+            .field("github_token", &"<redacted>")
+            .field("api_url", &self.api_url)
+            .finish()
+    }
+}
+
+/// Where the token of an account is read from, `token_source` in the config file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum TokenSource {
+    /// Secret Service keyring, then the token file, then the environment
+    #[default]
+    Auto,
+    SecretService,
+    File,
+    Env,
+}
+
+/// Which threads are notified, `poll_mode` in the config file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PollMode {
+    /// Every unread thread, whenever it is new or updated since it was last shown
+    #[default]
+    AllUnread,
+    /// Only threads updated after the last check, persisted once the popups are delivered
+    SinceLastCheck,
+    /// Only threads updated after they were last read on GitHub or acted on in a popup
+    SinceLastInteraction,
+}
+
+/// How threads arriving in the same poll are coalesced into one popup, `group_by` in the config file
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum GroupBy {
+    /// One popup per thread
+    #[default]
+    None,
+    /// One popup per repository
+    Repository,
+    /// One popup per issue, pull request or release, i.e. per `subject.url`
+    Subject,
+}
+
+/// What a popup dismissed by the user does to its thread, `delivery.on_close` in the config file.
+/// Popups that expire or are closed by the notification daemon always leave the thread unread.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ClosePolicy {
+    /// The thread stays unread on GitHub
+    #[default]
+    LeaveUnread,
+    /// The thread is marked as read, like the "Mark as read" action
+    MarkRead,
+    /// The popup comes back later, like the "Snooze 1h" action
+    Requeue,
+}
+
+/// Button of a popup, `popup.actions` in the config file. Clicking the popup itself always opens it in the browser.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PopupAction {
+    MarkRead,
+    Open,
+    /// Shows the popup again an hour later
+    #[serde(rename = "snooze-1h")]
+    Snooze1h,
+    /// Ignores the thread on GitHub and marks it read
+    Mute,
+    Unsubscribe,
+    /// Snoozes the repository for the shortest of `snooze.durations_days`
+    SnoozeRepo,
+    OpenRepo,
+}
+
+/// Reads the GitHub token of a single account
+pub trait TokenProvider {
+    /// Short description used in error messages
+    fn describe(&self) -> String;
+
+    /// `Ok(None)` when this provider has no token for the account
+    fn read_token(&self, account: &str) -> Result<Option<String>, ConfigError>;
+}
+
+/// Token stored in the freedesktop Secret Service (GNOME Keyring, KWallet, ...) over D-Bus
+pub struct SecretServiceTokenProvider;
+
+impl SecretServiceTokenProvider {
+    fn attributes(account: &str) -> HashMap<&str, &str> {
+        HashMap::from([("application", SECRET_SERVICE_APPLICATION), ("account", account)])
+    }
+
+    fn error(e: secret_service::Error) -> ConfigError {
+        ConfigError::Token(format!("Secret Service: {}", e))
+    }
+
+    pub fn store_token(&self, account: &str, token: &str) -> Result<(), ConfigError> {
+        let service = SecretService::connect(EncryptionType::Dh).map_err(Self::error)?;
+        let collection = service.get_default_collection().map_err(Self::error)?;
+        collection.ensure_unlocked().map_err(Self::error)?;
+        collection.create_item(
+            &format!("GitHub Notifier token ({})", account),
+            Self::attributes(account),
+            token.as_bytes(),
+            true,
+            "text/plain",
+        ).map_err(Self::error)?;
+        Ok(())
+    }
+}
+
+impl TokenProvider for SecretServiceTokenProvider {
+    fn describe(&self) -> String {
+        String::from("the Secret Service keyring")
+    }
+
+    fn read_token(&self, account: &str) -> Result<Option<String>, ConfigError> {
+        let service = SecretService::connect(EncryptionType::Dh).map_err(Self::error)?;
+        let items = service.search_items(Self::attributes(account)).map_err(Self::error)?;
+
+        let item = match items.unlocked.first() {
+            Some(item) => item,
+            None => match items.locked.first() {
+                Some(item) => {
+                    item.unlock().map_err(Self::error)?;
+                    item
+                }
+                None => return Ok(None),
+            },
+        };
+
+        let secret = item.get_secret().map_err(Self::error)?;
+        String::from_utf8(secret)
+            .map(|token| Some(token.trim().to_string()))
+            .map_err(|_| ConfigError::Token(format!("token of account {} in the Secret Service is not valid UTF-8", account)))
+    }
+}
+
+/// Token stored in a plain file which must only be accessible by its owner (0600)
+pub struct FileTokenProvider {
+    pub path: PathBuf,
+}
+
+impl FileTokenProvider {
+    /// `~/.config/github-notifier/tokens/<account>`
+    pub fn default_path(account: &str) -> PathBuf {
+        AppConfig::config_dir().join(TOKENS_DIR_NAME).join(account)
+    }
+
+    pub fn store_token(&self, token: &str) -> Result<(), ConfigError> {
+        if let Some(dir) = self.path.parent() {
+            fs::DirBuilder::new()
+                .recursive(true)
+                .mode(0o700)
+                .create(dir)
+                .map_err(|e| ConfigError::Io(dir.to_path_buf(), e))?;
+        }
+
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&self.path)
+            .map_err(|e| ConfigError::Io(self.path.clone(), e))?;
+        // mode() only applies to new files, tighten an existing one as well
+        file.set_permissions(fs::Permissions::from_mode(0o600))
+            .and_then(|_| file.write_all(token.as_bytes()))
+            .map_err(|e| ConfigError::Io(self.path.clone(), e))
+    }
+}
+
+impl TokenProvider for FileTokenProvider {
+    fn describe(&self) -> String {
+        format!("the file {}", self.path.display())
+    }
+
+    fn read_token(&self, _account: &str) -> Result<Option<String>, ConfigError> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(ConfigError::Io(self.path.clone(), e)),
+        };
+
+        if metadata.permissions().mode() & 0o077 != 0 {
+            return Err(ConfigError::Token(format!(
+                "{} is accessible by other users, restrict it with `chmod 600 {}`",
+                self.path.display(),
+                self.path.display()
+            )));
+        }
+
+        let token = fs::read_to_string(&self.path).map_err(|e| ConfigError::Io(self.path.clone(), e))?;
+        Ok(Some(token.trim().to_string()))
+    }
+}
+
+/// Token read from an environment variable
+pub struct EnvTokenProvider {
+    pub var: String,
+}
+
+impl TokenProvider for EnvTokenProvider {
+    fn describe(&self) -> String {
+        format!("the {} env variable", self.var)
+    }
+
+    fn read_token(&self, _account: &str) -> Result<Option<String>, ConfigError> {
+        Ok(std::env::var(&self.var).ok())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SnoozeConfig {
+    /// Durations offered in the tray "Snooze" submenu, in days
+    pub durations_days: Vec<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrayConfig {
+    pub show_repositories: bool,
+    /// Reasons offered in the tray "Snooze reasons" submenu
+    pub reasons: Vec<String>,
+}
+
+/// Pacing of popups: a burst goes out at once, then one every `notification_interval`
+#[derive(Debug, Clone)]
+pub struct DeliveryConfig {
+    pub burst: u32,
+    /// Reasons shown first when several popups are waiting, most important first
+    pub priority: Vec<String>,
+    /// Reasons shown with critical urgency
+    pub critical: Vec<String>,
+    /// Reasons shown with low urgency
+    pub low: Vec<String>,
+    /// Low urgency popups close after this delay, `None` keeps them until dismissed
+    pub low_timeout: Option<Duration>,
+    pub on_close: ClosePolicy,
+}
+
+impl DeliveryConfig {
+    /// Position of `reason` in the priority list, unlisted reasons come last
+    pub fn rank(&self, reason: &str) -> usize {
+        self.priority.iter().position(|r| r == reason).unwrap_or(self.priority.len())
+    }
+
+    pub fn urgency(&self, reason: &str) -> Urgency {
+        if self.critical.iter().any(|r| r == reason) {
+            Urgency::Critical
+        } else if self.low.iter().any(|r| r == reason) {
+            Urgency::Low
+        } else {
+            Urgency::Normal
+        }
+    }
+
+    pub fn timeout(&self, reason: &str) -> Timeout {
+        match (self.urgency(reason), self.low_timeout) {
+            (Urgency::Low, Some(timeout)) => Timeout::from(timeout),
+            _ => Timeout::Never,
+        }
+    }
+}
+
+/// Buttons of the single thread popups, after the quick actions and the reply box.
+/// Most notification servers show only the first three.
+#[derive(Debug, Clone)]
+pub struct PopupConfig {
+    pub actions: Vec<PopupAction>,
+}
+
+/// Popup actions acting on the subject itself, each offered only for the listed reasons and confirmed first
+#[derive(Debug, Clone)]
+pub struct QuickActionsConfig {
+    /// Reasons whose open pull requests can be approved
+    pub approve: Vec<String>,
+    /// Reasons whose pull requests can be merged once mergeable with green checks
+    pub merge: Vec<String>,
+    /// Reasons whose latest comment can get a reaction
+    pub react: Vec<String>,
+    /// Reaction added by the react action, one of `ALL_REACTIONS`
+    pub reaction: String,
+}
+
+/// Author avatars shown as popup images, cached under `~/.config/github-notifier/avatars`
+#[derive(Debug, Clone)]
+pub struct AvatarConfig {
+    pub enabled: bool,
+    /// Cached avatars older than this are downloaded again
+    pub ttl: Duration,
+}
+
+/// Batches new threads into a single summary popup instead of one popup per thread
+#[derive(Debug, Clone)]
+pub struct DigestConfig {
+    pub enabled: bool,
+    /// Time between two digests, used when no `times` are configured
+    pub window: Duration,
+    /// Local times of day at which a digest is shown, sorted
+    pub times: Vec<NaiveTime>,
+}
+
+impl DigestConfig {
+    /// When the digest following `now` is due
+    pub fn next_after(&self, now: DateTime<Local>) -> DateTime<Local> {
+        let today = now.date_naive();
+        let next = self.times.iter()
+            .map(|time| today.and_time(*time))
+            .find(|at| *at > now.naive_local())
+            .or_else(|| self.times.first().map(|time| today.succ_opt().unwrap_or(today).and_time(*time)));
+
+        match next {
+            // Times skipped by a DST change fall back to the window
+            Some(next) => next.and_local_timezone(Local).earliest().unwrap_or(now + self.window),
+            None => now + self.window,
+        }
+    }
+}
+
+/// Weekly time range during which popups are held back
+#[derive(Debug, Clone)]
+pub struct QuietRange {
+    /// Days on which the range starts
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    /// An end before `start` falls on the next day
+    pub end: NaiveTime,
+}
+
+impl QuietRange {
+    fn contains(&self, at: NaiveDateTime) -> bool {
+        let (day, time) = (at.weekday(), at.time());
+        if self.start < self.end {
+            self.days.contains(&day) && self.start <= time && time < self.end
+        } else {
+            (self.days.contains(&day) && time >= self.start) || (self.days.contains(&day.pred()) && time < self.end)
+        }
+    }
+}
+
+/// Schedule during which threads are still polled and recorded but their popups are held back,
+/// they are shown as one summary once the quiet period ends
+#[derive(Debug, Clone)]
+pub struct QuietHoursConfig {
+    /// Zone the ranges are expressed in, the system time zone when `None`
+    pub timezone: Option<Tz>,
+    pub ranges: Vec<QuietRange>,
+}
+
+impl QuietHoursConfig {
+    pub fn is_quiet(&self, now: DateTime<Utc>) -> bool {
+        let at = match self.timezone {
+            Some(timezone) => now.with_timezone(&timezone).naive_local(),
+            None => now.with_timezone(&Local).naive_local(),
+        };
+        self.ranges.iter().any(|range| range.contains(at))
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct AppConfig {
-    pub github_token: String
+    pub accounts: Vec<AccountConfig>,
+    pub poll_interval: Duration,
+    pub poll_mode: PollMode,
+    pub group_by: GroupBy,
+    /// Pause between two consecutive popups
+    pub notification_interval: Duration,
+    /// Notifications requested per page, GitHub allows at most 50
+    pub page_size: u32,
+    /// Upper bound of pages fetched in a single poll
+    pub max_pages: u32,
+    pub assets_dir: PathBuf,
+    pub db_path: PathBuf,
+    pub snooze: SnoozeConfig,
+    pub tray: TrayConfig,
+    pub digest: DigestConfig,
+    pub delivery: DeliveryConfig,
+    pub avatars: AvatarConfig,
+    pub popup: PopupConfig,
+    pub quick_actions: QuickActionsConfig,
+    pub quiet_hours: QuietHoursConfig,
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Invalid(String),
+    Token(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "Failed to read {}: {}", path.display(), e),
+            ConfigError::Parse(path, e) => write!(f, "Failed to parse {}: {}", path.display(), e.message()),
+            ConfigError::Invalid(msg) => write!(f, "Invalid configuration: {}", msg),
+            ConfigError::Token(msg) => write!(f, "Failed to load GitHub token: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Layout of `~/.config/github-notifier/config.toml`, every key is optional:
+///
+/// ```toml
+/// poll_interval_seconds = 60           # how often notifications are fetched
+/// poll_mode = "all-unread"             # all-unread, since-last-check or since-last-interaction
+/// group_by = "none"                    # none, repository or subject
+/// notification_interval_seconds = 12   # pause between two popups
+/// page_size = 50                       # notifications per page, at most 50
+/// max_pages = 10                       # pages fetched per poll
+/// assets_dir = "./assets"              # icons, relative to the working directory
+/// database = "config.db"               # relative to ~/.config/github-notifier
+/// api_url = "https://api.github.com"   # default for accounts without their own api_url
+///
+/// [snooze]
+/// durations_days = [1, 7, 30]
+///
+/// [tray]
+/// show_repositories = true
+/// reasons = ["mention", "review_requested", "ci_activity"]
+///
+/// [delivery]
+/// burst = 1                            # popups shown at once before notification_interval_seconds applies
+/// priority = ["review_requested", "mention"]
+/// critical = ["review_requested", "mention", "assign"]
+/// low = ["subscribed", "ci_activity"]
+/// low_timeout_seconds = 10             # low urgency popups close by themselves
+/// on_close = "leave-unread"            # leave-unread, mark-read or requeue, when a popup is dismissed
+///
+/// [popup]
+/// actions = ["mark-read", "open", "snooze-1h"]   # also mute, unsubscribe, snooze-repo and open-repo
+///
+/// [avatars]
+/// enabled = true
+/// ttl_days = 7
+///
+/// [quick_actions]
+/// approve = ["review_requested"]       # reasons offering each action, none by default
+/// merge = ["review_requested"]
+/// react = ["mention", "comment"]
+/// reaction = "+1"                      # +1, -1, laugh, confused, heart, hooray, rocket or eyes
+///
+/// [quiet_hours]
+/// timezone = "Europe/Berlin"           # IANA name, the system time zone by default
+///
+/// [[quiet_hours.ranges]]
+/// days = ["mon", "tue", "wed", "thu", "fri"]   # every day by default
+/// start = "19:00"
+/// end = "08:00"                        # before start: ends the next day
+///
+/// [digest]
+/// enabled = false
+/// window_minutes = 30                  # used when no times are given
+/// times = ["09:00", "14:00"]           # local times of day
+///
+/// [[accounts]]
+/// name = "work"
+/// api_url = "https://github.example.com/api/v3"
+/// token_source = "auto"                # auto, secret-service, file or env
+/// token_file = "/path/to/token"        # defaults to ~/.config/github-notifier/tokens/<name>
+/// token_env = "GITHUB_TOKEN_WORK"
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ConfigFile {
+    poll_interval_seconds: Option<u64>,
+    poll_mode: PollMode,
+    group_by: GroupBy,
+    notification_interval_seconds: Option<u64>,
+    page_size: Option<u32>,
+    max_pages: Option<u32>,
+    assets_dir: Option<PathBuf>,
+    database: Option<PathBuf>,
+    api_url: Option<String>,
+    snooze: SnoozeSection,
+    tray: TraySection,
+    digest: DigestSection,
+    delivery: DeliverySection,
+    avatars: AvatarsSection,
+    popup: PopupSection,
+    quick_actions: QuickActionsSection,
+    quiet_hours: QuietHoursSection,
+    accounts: Vec<AccountSection>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SnoozeSection {
+    durations_days: Option<Vec<u32>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct TraySection {
+    show_repositories: Option<bool>,
+    reasons: Option<Vec<String>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DeliverySection {
+    burst: Option<u32>,
+    priority: Option<Vec<String>>,
+    critical: Option<Vec<String>>,
+    low: Option<Vec<String>>,
+    low_timeout_seconds: Option<u64>,
+    on_close: ClosePolicy,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AvatarsSection {
+    enabled: Option<bool>,
+    ttl_days: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PopupSection {
+    actions: Option<Vec<PopupAction>>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct QuickActionsSection {
+    approve: Option<Vec<String>>,
+    merge: Option<Vec<String>>,
+    react: Option<Vec<String>>,
+    reaction: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct QuietHoursSection {
+    timezone: Option<String>,
+    ranges: Vec<QuietRangeSection>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct QuietRangeSection {
+    days: Option<Vec<String>>,
+    start: String,
+    end: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DigestSection {
+    enabled: Option<bool>,
+    window_minutes: Option<u64>,
+    times: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AccountSection {
+    name: String,
+    api_url: Option<String>,
+    #[serde(default)]
+    token_source: TokenSource,
+    /// File holding the token, defaults to `~/.config/github-notifier/tokens/<name>`
+    token_file: Option<PathBuf>,
+    /// Environment variable holding the token, defaults to `GITHUB_TOKEN_<NAME>`
+    token_env: Option<String>,
 }
 
 impl AppConfig {
-    pub fn load() -> Self {
-        let github_token = std::env::var("GITHUB_TOKEN");
-        match github_token {
-            Ok(github_token) => {
-                AppConfig {
-                    github_token
-                }
+    /// Directory holding the config file, the database and the last check timestamp
+    pub fn config_dir() -> PathBuf {
+        dirs::home_dir()
+            .expect("Failed to get home directory")
+            .join(CONFIG_DIR_NAME)
+    }
+
+    /// Loads `config.toml` from the config dir, or the file named by `GITHUB_NOTIFIER_CONFIG`.
+    /// A missing file is not an error, every setting has a default.
+    ///
+    /// When the file lists no accounts they are read from the environment: `GITHUB_ACCOUNTS`
+    /// holds a comma separated list of account names, each taking its API URL from
+    /// `GITHUB_API_URL_<NAME>`. Without `GITHUB_ACCOUNTS` a single `default` account is used.
+    /// Tokens of those accounts are looked up with [`TokenSource::Auto`], the env fallback being
+    /// `GITHUB_TOKEN_<NAME>`, or `GITHUB_TOKEN` for the default account.
+    pub fn load() -> Result<Self, ConfigError> {
+        let path = std::env::var_os("GITHUB_NOTIFIER_CONFIG")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| Self::config_dir().join(CONFIG_FILE_NAME));
+
+        let file = match std::fs::read_to_string(&path) {
+            Ok(content) => toml::from_str::<ConfigFile>(&content).map_err(|e| ConfigError::Parse(path.clone(), e))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => ConfigFile::default(),
+            Err(e) => return Err(ConfigError::Io(path, e)),
+        };
+
+        Self::from_file(file)
+    }
+
+    #[cfg(test)]
+    pub fn from_toml(content: &str) -> Result<Self, ConfigError> {
+        let file = toml::from_str::<ConfigFile>(content).map_err(|e| ConfigError::Parse(PathBuf::from("config.toml"), e))?;
+        Self::from_file(file)
+    }
+
+    fn from_file(file: ConfigFile) -> Result<Self, ConfigError> {
+        let default_api_url = file.api_url
+            .or_else(|| std::env::var("GITHUB_API_URL").ok())
+            .unwrap_or_else(|| String::from(DEFAULT_API_URL));
+
+        let accounts = if file.accounts.is_empty() {
+            Self::accounts_from_env(&default_api_url)?
+        } else {
+            file.accounts.into_iter()
+                .map(|account| {
+                    let token_env = account.token_env
+                        .unwrap_or_else(|| format!("GITHUB_TOKEN_{}", Self::env_suffix(&account.name)));
+                    let api_url = account.api_url.unwrap_or_else(|| default_api_url.clone());
+                    Self::load_account(&account.name, account.token_source, account.token_file, token_env, api_url)
+                })
+                .collect::<Result<_, _>>()?
+        };
+
+        let mut times = file.digest.times.unwrap_or_default()
+            .iter()
+            .map(|time| NaiveTime::parse_from_str(time, "%H:%M")
+                .map_err(|_| ConfigError::Invalid(format!("digest.times entry {:?} is not a HH:MM time", time))))
+            .collect::<Result<Vec<_>, _>>()?;
+        times.sort();
+
+        let timezone = file.quiet_hours.timezone
+            .map(|timezone| timezone.parse::<Tz>()
+                .map_err(|_| ConfigError::Invalid(format!("quiet_hours.timezone {:?} is not an IANA time zone", timezone))))
+            .transpose()?;
+        let quiet_time = |time: &str| NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| ConfigError::Invalid(format!("quiet_hours.ranges time {:?} is not a HH:MM time", time)));
+        let quiet_ranges = file.quiet_hours.ranges.iter()
+            .map(|range| {
+                let days = match &range.days {
+                    Some(days) => days.iter()
+                        .map(|day| day.parse::<Weekday>()
+                            .map_err(|_| ConfigError::Invalid(format!("quiet_hours.ranges day {:?} is not a weekday", day))))
+                        .collect::<Result<Vec<_>, _>>()?,
+                    None => vec![Weekday::Mon, Weekday::Tue, Weekday::Wed, Weekday::Thu, Weekday::Fri, Weekday::Sat, Weekday::Sun],
+                };
+                Ok(QuietRange { days, start: quiet_time(&range.start)?, end: quiet_time(&range.end)? })
+            })
+            .collect::<Result<Vec<_>, ConfigError>>()?;
+
+        let config = AppConfig {
+            accounts,
+            poll_interval: Duration::from_secs(file.poll_interval_seconds.unwrap_or(60)),
+            poll_mode: file.poll_mode,
+            group_by: file.group_by,
+            notification_interval: Duration::from_secs(file.notification_interval_seconds.unwrap_or(12)),
+            page_size: file.page_size.unwrap_or(50),
+            max_pages: file.max_pages.unwrap_or(10),
+            assets_dir: file.assets_dir.unwrap_or_else(|| PathBuf::from(DEFAULT_ASSETS_DIR)),
+            db_path: Self::config_dir().join(file.database.unwrap_or_else(|| PathBuf::from("config.db"))),
+            snooze: SnoozeConfig {
+                durations_days: file.snooze.durations_days.unwrap_or_else(|| vec![1, 7, 30]),
+            },
+            tray: TrayConfig {
+                show_repositories: file.tray.show_repositories.unwrap_or(true),
+                reasons: file.tray.reasons
+                    .unwrap_or_else(|| ALL_REASONS.iter().map(|r| r.to_string()).collect()),
+            },
+            digest: DigestConfig {
+                enabled: file.digest.enabled.unwrap_or(false),
+                window: Duration::from_secs(file.digest.window_minutes.unwrap_or(30) * 60),
+                times,
+            },
+            delivery: DeliveryConfig {
+                burst: file.delivery.burst.unwrap_or(1),
+                priority: file.delivery.priority
+                    .unwrap_or_else(|| DEFAULT_PRIORITY.iter().map(|r| r.to_string()).collect()),
+                critical: file.delivery.critical
+                    .unwrap_or_else(|| ["review_requested", "mention", "assign"].iter().map(|r| r.to_string()).collect()),
+                low: file.delivery.low
+                    .unwrap_or_else(|| ["subscribed", "ci_activity"].iter().map(|r| r.to_string()).collect()),
+                low_timeout: file.delivery.low_timeout_seconds.map(Duration::from_secs),
+                on_close: file.delivery.on_close,
+            },
+            avatars: AvatarConfig {
+                enabled: file.avatars.enabled.unwrap_or(true),
+                ttl: Duration::from_secs(file.avatars.ttl_days.unwrap_or(7) * 24 * 60 * 60),
+            },
+            popup: PopupConfig {
+                actions: file.popup.actions
+                    .unwrap_or_else(|| vec![PopupAction::MarkRead, PopupAction::Open, PopupAction::Snooze1h]),
+            },
+            quick_actions: QuickActionsConfig {
+                approve: file.quick_actions.approve.unwrap_or_default(),
+                merge: file.quick_actions.merge.unwrap_or_default(),
+                react: file.quick_actions.react.unwrap_or_default(),
+                reaction: file.quick_actions.reaction.unwrap_or_else(|| "+1".to_string()),
+            },
+            quiet_hours: QuietHoursConfig {
+                timezone,
+                ranges: quiet_ranges,
+            },
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        if self.poll_interval.is_zero() {
+            return Err(ConfigError::Invalid("poll_interval_seconds must be greater than 0".into()));
+        }
+        if !(1..=50).contains(&self.page_size) {
+            return Err(ConfigError::Invalid(format!("page_size must be between 1 and 50, got {}", self.page_size)));
+        }
+        if self.max_pages == 0 {
+            return Err(ConfigError::Invalid("max_pages must be greater than 0".into()));
+        }
+        if self.accounts.is_empty() {
+            return Err(ConfigError::Invalid("at least one account must be configured".into()));
+        }
+
+        let mut names = HashSet::new();
+        for account in &self.accounts {
+            if account.name.is_empty() || account.name.contains(':') {
+                return Err(ConfigError::Invalid(format!("account name {:?} must be non-empty and must not contain ':'", account.name)));
             }
-            Err(e) => {
-                Self::notify_config_issue(&format!("Failed to load GITHUB_TOKEN env variable: {}", e));
-                AppConfig {
-                    github_token: String::from("")
-                }
+            if !names.insert(account.name.as_str()) {
+                return Err(ConfigError::Invalid(format!("account {} is configured more than once", account.name)));
+            }
+            if !account.api_url.starts_with("http://") && !account.api_url.starts_with("https://") {
+                return Err(ConfigError::Invalid(format!("api_url {:?} of account {} is not an http(s) URL", account.api_url, account.name)));
             }
         }
 
+        if !self.assets_dir.is_dir() {
+            return Err(ConfigError::Invalid(format!("assets_dir {} is not a directory", self.assets_dir.display())));
+        }
+        if self.snooze.durations_days.contains(&0) {
+            return Err(ConfigError::Invalid("snooze.durations_days must only contain positive values".into()));
+        }
+        if let Some(reason) = self.tray.reasons.iter().find(|r| !ALL_REASONS.contains(&r.as_str())) {
+            return Err(ConfigError::Invalid(format!("tray.reasons contains unknown reason {:?}, expected one of {}", reason, ALL_REASONS.join(", "))));
+        }
+        if self.delivery.burst == 0 {
+            return Err(ConfigError::Invalid("delivery.burst must be greater than 0".into()));
+        }
+        for (key, reasons) in [("priority", &self.delivery.priority), ("critical", &self.delivery.critical), ("low", &self.delivery.low)] {
+            if let Some(reason) = reasons.iter().find(|r| !ALL_REASONS.contains(&r.as_str())) {
+                return Err(ConfigError::Invalid(format!("delivery.{} contains unknown reason {:?}, expected one of {}", key, reason, ALL_REASONS.join(", "))));
+            }
+        }
+        if self.delivery.low_timeout.is_some_and(|timeout| timeout.is_zero()) {
+            return Err(ConfigError::Invalid("delivery.low_timeout_seconds must be greater than 0".into()));
+        }
+        for (key, reasons) in [("approve", &self.quick_actions.approve), ("merge", &self.quick_actions.merge), ("react", &self.quick_actions.react)] {
+            if let Some(reason) = reasons.iter().find(|r| !ALL_REASONS.contains(&r.as_str())) {
+                return Err(ConfigError::Invalid(format!("quick_actions.{} contains unknown reason {:?}, expected one of {}", key, reason, ALL_REASONS.join(", "))));
+            }
+        }
+        if !ALL_REACTIONS.contains(&self.quick_actions.reaction.as_str()) {
+            return Err(ConfigError::Invalid(format!("quick_actions.reaction {:?} is not one of {}", self.quick_actions.reaction, ALL_REACTIONS.join(", "))));
+        }
+        if self.quiet_hours.ranges.iter().any(|range| range.start == range.end) {
+            return Err(ConfigError::Invalid("quiet_hours.ranges must not start and end at the same time".into()));
+        }
+        if self.digest.window.is_zero() {
+            return Err(ConfigError::Invalid("digest.window_minutes must be greater than 0".into()));
+        }
+        Ok(())
+    }
+
+    /// Path of an icon inside `assets_dir`
+    pub fn asset(&self, file_name: &str) -> PathBuf {
+        self.assets_dir.join(file_name)
+    }
 
+    fn accounts_from_env(default_api_url: &str) -> Result<Vec<AccountConfig>, ConfigError> {
+        match std::env::var("GITHUB_ACCOUNTS") {
+            Ok(names) => {
+                names.split(',')
+                    .map(str::trim)
+                    .filter(|name| !name.is_empty())
+                    .map(|name| {
+                        let suffix = Self::env_suffix(name);
+                        Self::load_account(
+                            name,
+                            TokenSource::Auto,
+                            None,
+                            format!("GITHUB_TOKEN_{}", suffix),
+                            std::env::var(format!("GITHUB_API_URL_{}", suffix)).unwrap_or_else(|_| default_api_url.to_string()),
+                        )
+                    })
+                    .collect()
+            }
+            Err(_) => Ok(vec![Self::load_account(DEFAULT_ACCOUNT_NAME, TokenSource::Auto, None, String::from("GITHUB_TOKEN"), default_api_url.to_string())?])
+        }
     }
 
-    fn notify_config_issue(msg: &str) {
-        Notification::new()
-            .summary("Configuration issue")
-            .body(&msg)
-            .image("./assets/github-warning.png")
-            .unwrap()
-            .show();
+    fn load_account(
+        name: &str,
+        token_source: TokenSource,
+        token_file: Option<PathBuf>,
+        token_env: String,
+        api_url: String,
+    ) -> Result<AccountConfig, ConfigError> {
+        let file = FileTokenProvider { path: token_file.unwrap_or_else(|| FileTokenProvider::default_path(name)) };
+        let env = EnvTokenProvider { var: token_env };
+
+        let providers: Vec<&dyn TokenProvider> = match token_source {
+            TokenSource::Auto => vec![&SecretServiceTokenProvider, &file, &env],
+            TokenSource::SecretService => vec![&SecretServiceTokenProvider],
+            TokenSource::File => vec![&file],
+            TokenSource::Env => vec![&env],
+        };
+
+        let github_token = Self::read_token(name, &providers, token_source == TokenSource::Auto)?;
+
+        Ok(AccountConfig {
+            name: name.to_string(),
+            github_token,
+            api_url: api_url.trim_end_matches('/').to_string(),
+        })
     }
-}
\ No newline at end of file
+
+    /// Asks the providers in order and returns the first token found. With `lenient` set a
+    /// failing provider, e.g. no Secret Service running, is skipped instead of aborting the lookup.
+    fn read_token(account: &str, providers: &[&dyn TokenProvider], lenient: bool) -> Result<String, ConfigError> {
+        for provider in providers {
+            match provider.read_token(account) {
+                Ok(Some(token)) if !token.is_empty() => return Ok(token),
+                Ok(_) => {}
+                Err(e) if lenient => eprintln!("Skipping {} for account {}: {}", provider.describe(), account, e),
+                Err(e) => return Err(e),
+            }
+        }
+
+        let tried: Vec<String> = providers.iter().map(|p| p.describe()).collect();
+        Err(ConfigError::Token(format!(
+            "no token found for account {} in {}, store one with `github-notifier auth set-token --account {}`",
+            account,
+            tried.join(", "),
+            account
+        )))
+    }
+
+    /// `work-ghe` -> `WORK_GHE`
+    fn env_suffix(name: &str) -> String {
+        name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect()
+    }
+
+    /// Popup for a config that cannot be loaded. It runs before `assets_dir` is known to exist,
+    /// so a missing icon or notification server is only logged.
+    pub fn notify_config_issue(assets_dir: &Path, msg: &str) {
+        let mut popup = Notification::new();
+        popup.summary("Configuration issue").body(msg);
+        if let Err(e) = popup.image(assets_dir.join("github-warning.png")) {
+            eprintln!("Failed to load popup image: {}", e);
+        }
+        if let Err(e) = popup.show() {
+            eprintln!("Failed to show configuration issue: {}", e);
+        }
+    }
+}
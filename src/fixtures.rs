@@ -0,0 +1,19 @@
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use crate::github::github::NotificationDto;
+
+/// Raw `tests/fixtures/<dir>/<name>.json`
+pub fn fixture(dir: &str, name: &str) -> String {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures").join(dir).join(format!("{}.json", name));
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("Failed to read {}: {}", path.display(), e))
+}
+
+/// Thread of the notifications list, `tests/fixtures/notifications/<name>.json`
+pub fn notification(name: &str) -> NotificationDto {
+    serde_json::from_str(&fixture("notifications", name)).unwrap()
+}
+
+/// Subject details, `tests/fixtures/details/<name>.json`
+pub fn details<T: DeserializeOwned>(name: &str) -> T {
+    serde_json::from_str(&fixture("details", name)).unwrap()
+}
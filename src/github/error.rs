@@ -0,0 +1,51 @@
+use std::fmt;
+use std::time::Duration;
+use crate::github::github::RateLimit;
+
+/// Failure of a GitHub API call
+#[derive(Debug)]
+pub enum GithubError {
+    /// 401, or 403 without rate limit hints: the token is missing, revoked or lacks a scope
+    Auth(u16),
+    /// Rejected by the primary or secondary rate limit, nothing should be sent before `retry_after`
+    RateLimited {
+        retry_after: Duration,
+        rate_limit: Option<RateLimit>,
+    },
+    /// The resource is gone or not visible to the token
+    NotFound,
+    /// The request never got an answer: DNS, connection, TLS or timeout
+    Network(String),
+    /// The answer could not be deserialized
+    Decode(String),
+    /// 5xx answer
+    Server(u16),
+    /// Any other non-2xx answer
+    Unexpected(u16),
+    /// The HTTP client could not be set up, e.g. the token is not a valid header value
+    Client(String),
+}
+
+impl GithubError {
+    /// Worth retrying with a backoff
+    pub fn is_transient(&self) -> bool {
+        matches!(self, GithubError::Network(_) | GithubError::Server(_))
+    }
+}
+
+impl fmt::Display for GithubError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GithubError::Auth(status) => write!(f, "GitHub rejected the token ({}), check that it is valid and has the notifications and repo scopes", status),
+            GithubError::RateLimited { retry_after, .. } => write!(f, "GitHub API rate limit exceeded, retry in {}s", retry_after.as_secs()),
+            GithubError::NotFound => write!(f, "Resource not found"),
+            GithubError::Network(e) => write!(f, "Request error: {}", e),
+            GithubError::Decode(e) => write!(f, "Error while parsing response body: {}", e),
+            GithubError::Server(status) => write!(f, "GitHub API returned server error {}", status),
+            GithubError::Unexpected(status) => write!(f, "GitHub API returned status {}", status),
+            GithubError::Client(e) => write!(f, "Failed to set up the GitHub client: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for GithubError {}
@@ -1,189 +1,601 @@
-use notify_rust::Notification;
-use ureq::{Body, Error, RequestBuilder};
-use ureq::http::Response;
+use std::time::Duration;
+use chrono::{DateTime, TimeZone, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, ACCEPT, AUTHORIZATION, IF_MODIFIED_SINCE, USER_AGENT};
+use reqwest::{RequestBuilder, Response, StatusCode};
 use time;
-use crate::app_config::AppConfig;
+use crate::app_config::{AccountConfig, AppConfig};
+use crate::github::error::GithubError;
 
-#[derive(Debug, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct NotificationDto {
     pub id: String,
     pub reason: String,
     pub repository: Repository,
     pub subject: Subject,
+    /// API URL of the thread
+    pub url: String,
+    pub unread: bool,
+    /// Last activity on the thread, a new comment or review moves it forward
+    pub updated_at: DateTime<Utc>,
+    /// When the thread was last read on GitHub, `None` if never
+    pub last_read_at: Option<DateTime<Utc>>,
+}
+
+impl NotificationDto {
+    /// The thread was read, on GitHub or through another client, after its last activity
+    pub fn is_read_since_update(&self) -> bool {
+        !self.unread || matches!(self.last_read_at, Some(read) if read >= self.updated_at)
+    }
 }
 
 
-#[derive(Debug, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct Repository {
     pub full_name: String,
     pub owner: Owner,
 }
 
-#[derive(Debug, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct Owner {
     pub login: String,
 }
 
-#[derive(Debug, serde::Deserialize, Clone)]
+#[derive(Debug, serde::Deserialize, serde::Serialize, Clone)]
 pub struct Subject {
     pub title: String,
+    /// `PullRequest`, `Issue`, `Release`, `Discussion`, `CheckSuite`, `Commit`, `RepositoryVulnerabilityAlert`, ...
     #[serde(rename = "type")]
     pub type_field: String,
 
-    pub url: String,
+    /// API URL of the subject, null for discussions, check suites and vulnerability alerts
+    pub url: Option<String>,
     pub latest_comment_url: Option<String>,
 }
 
-#[derive(Debug, serde::Deserialize, Clone)]
-pub struct NotificationDetailDto {
+impl Subject {
+    /// Number of the issue or pull request, the last segment of `url`
+    pub fn number(&self) -> Option<&str> {
+        self.url.as_deref()?.rsplit('/').next().filter(|number| !number.is_empty())
+    }
+}
 
+/// Details of the subject of a thread, fetched from `subject.url`
+#[derive(Debug, Clone)]
+pub enum NotificationDetailDto {
+    PullRequest(PullRequestDetailDto),
+    Issue(IssueDetailDto),
+    Release(ReleaseDetailDto),
+    Commit(CommitDetailDto),
+}
+
+impl NotificationDetailDto {
+    /// Page of the subject on the web UI
+    pub fn html_url(&self) -> Option<&str> {
+        match self {
+            NotificationDetailDto::PullRequest(pr) => pr.html_url.as_deref(),
+            NotificationDetailDto::Issue(issue) => issue.html_url.as_deref(),
+            NotificationDetailDto::Release(release) => release.html_url.as_deref(),
+            NotificationDetailDto::Commit(commit) => commit.html_url.as_deref(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct PullRequestDetailDto {
     pub state: String,
+    #[serde(default)]
     pub merged: bool,
+    pub html_url: Option<String>,
+    /// Author of the pull request
+    pub user: Option<UserDto>,
+    pub additions: Option<u32>,
+    pub deletions: Option<u32>,
+    #[serde(default)]
+    pub draft: bool,
+    #[serde(default)]
+    pub labels: Vec<LabelDto>,
+    /// `clean` once the pull request is mergeable with every required check green
+    pub mergeable_state: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct IssueDetailDto {
+    pub state: String,
+    /// `completed`, `not_planned` or `reopened`
+    pub state_reason: Option<String>,
+    pub html_url: Option<String>,
+    /// Author of the issue
+    pub user: Option<UserDto>,
+    #[serde(default)]
+    pub labels: Vec<LabelDto>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct UserDto {
+    pub login: String,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct LabelDto {
+    pub name: String,
+}
 
-    #[serde(rename = "_links")]
-    pub links: NotificationDetailLinks,
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct ReleaseDetailDto {
+    pub tag_name: Option<String>,
+    #[serde(default)]
+    pub prerelease: bool,
+    pub html_url: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize, Clone)]
+pub struct CommitDetailDto {
+    pub html_url: Option<String>,
 }
 
 #[derive(Debug, serde::Deserialize, Clone)]
 pub struct CommentDto {
     #[serde(rename = "html_url")]
     pub url: String,
+    /// Markdown source of the comment
+    pub body: Option<String>,
+    pub user: Option<UserDto>,
 }
 
-#[derive(Debug, serde::Deserialize, Clone)]
-pub struct NotificationDetailLinks {
-    pub html: NotificationDetailLinkHref,
+/// Result of a conditional poll of the notifications endpoint
+#[derive(Debug)]
+pub struct NotificationsPoll {
+    /// `None` when GitHub answered 304 Not Modified, i.e. nothing changed since `last_modified`
+    pub notifications: Option<Vec<NotificationDto>>,
+    /// `Last-Modified` header to send back as `If-Modified-Since` on the next poll
+    pub last_modified: Option<String>,
+    /// Minimum delay before the next poll requested by GitHub through `X-Poll-Interval`
+    pub poll_interval: Option<Duration>,
+    /// Quota left, as reported by the last response
+    pub rate_limit: Option<RateLimit>,
+    /// Pages were left unfetched after `max_pages`, the older threads of the poll are missing
+    pub truncated: bool,
 }
 
-#[derive(Debug, serde::Deserialize, Clone)]
-pub struct NotificationDetailLinkHref {
-    pub href: String,
+/// `X-RateLimit-*` headers of a response
+#[derive(Debug, Clone)]
+pub struct RateLimit {
+    pub limit: Option<u32>,
+    pub remaining: u32,
+    pub reset: DateTime<Utc>,
+}
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Async client for the GitHub API of one account.
+/// Cloning is cheap, clones share the connection pool and the authentication headers.
+#[derive(Debug, Clone)]
+pub struct GithubClient {
+    http: reqwest::Client,
+    account: AccountConfig,
+    page_size: u32,
+    max_pages: u32,
 }
 
-fn notify_error(title: &str, msg: String ){
-    Notification::new()
-        .summary(&title)
-        .body(&msg)
-        .image("./assets/github-error.png")
-        .unwrap()
-        .show();
+fn rate_limit(response: &Response) -> Option<RateLimit> {
+    let remaining = header_value(response, "X-RateLimit-Remaining")?.parse::<u32>().ok()?;
+    let reset = header_value(response, "X-RateLimit-Reset")?.parse::<i64>().ok()?;
+
+    Some(RateLimit {
+        limit: header_value(response, "X-RateLimit-Limit").and_then(|limit| limit.parse::<u32>().ok()),
+        remaining,
+        reset: Utc.timestamp_opt(reset, 0).single()?,
+    })
 }
-fn notify_warning(title: &str, msg: String ){
-    Notification::new()
-        .summary(&title)
-        .body(&msg)
-        .image("./assets/github-warning.png")
-        .unwrap()
-        .show();
+
+// How long to wait when the response is a rejection by the primary or secondary rate limit.
+// GitHub answers those with 403 or 429 and either `Retry-After` or an exhausted quota.
+fn rate_limit_wait(response: &Response) -> Option<Duration> {
+    let status = response.status();
+    if status != StatusCode::FORBIDDEN && status != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+
+    if let Some(retry_after) = header_value(response, "Retry-After").and_then(|seconds| seconds.parse::<u64>().ok()) {
+        return Some(Duration::from_secs(retry_after));
+    }
+
+    match rate_limit(response) {
+        Some(limit) if limit.remaining == 0 => Some((limit.reset - Utc::now()).to_std().unwrap_or(Duration::ZERO)),
+        // Secondary rate limits without a hint, GitHub asks to wait at least a minute
+        _ if status == StatusCode::TOO_MANY_REQUESTS => Some(Duration::from_secs(60)),
+        _ => None,
+    }
+}
+
+fn request_error(e: reqwest::Error) -> GithubError {
+    if e.is_decode() {
+        GithubError::Decode(e.to_string())
+    } else {
+        GithubError::Network(e.to_string())
+    }
+}
+
+// Maps a transport failure or a non-2xx answer to a `GithubError`, successful responses are passed through.
+fn check_response(res: Result<Response, reqwest::Error>) -> Result<Response, GithubError> {
+    let response = res.map_err(request_error)?;
+    let status = response.status();
+
+    if status.is_success() || status == StatusCode::NOT_MODIFIED {
+        return Ok(response);
+    }
+
+    if let Some(retry_after) = rate_limit_wait(&response) {
+        return Err(GithubError::RateLimited {
+            retry_after,
+            rate_limit: rate_limit(&response),
+        });
+    }
+
+    Err(match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => GithubError::Auth(status.as_u16()),
+        StatusCode::NOT_FOUND => GithubError::NotFound,
+        status if status.is_server_error() => GithubError::Server(status.as_u16()),
+        status => GithubError::Unexpected(status.as_u16()),
+    })
 }
 
 // This function processes the response received from the API call.
 // It tries to deserialize the response body into a desired type `T` (generic and must implement `DeserializeOwned`).
-// If the response is successful and contains a JSON body, it parses it into `Vec<Notification>` in this context.
-// Otherwise, if there's an error (e.g., HTTP error or deserialization issue), it logs the error and returns the provided `default` value.
-fn process_response<T>(res: Result<Response<Body>, Error>, default: T) -> T
+// Failed requests, error statuses and bodies that do not match `T` are reported as `GithubError`.
+async fn process_response<T>(res: Result<Response, reqwest::Error>) -> Result<T, GithubError>
 where
     T: serde::de::DeserializeOwned,
 {
-    match res {
-        Ok(mut response) => {
-            if response.status().is_success() {
-                let parsed = response
-                    .into_body()
-                    .read_json::<T>();
-
-                match parsed {
-                    Ok(parsed) => parsed,
-                    Err(e) => {
-                        notify_error("Parsing error!", format!("There was error while processing the API response: {:?}", e));
-
-                        eprintln!("Error while parsing response body: {}", e);
-                        default
-                    }
-                }
-            } else {
-                eprintln!("GitHub API returned error: {}", response.status());
-                notify_error("GitHub API returned error", format!("There was an error when sending request to Github: {:?}", response.status().to_string()));
-                default
-            }
+    check_response(res)?
+        .json::<T>()
+        .await
+        .map_err(|e| GithubError::Decode(e.to_string()))
+}
+
+fn header_value(response: &Response, name: &str) -> Option<String> {
+    response.headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string)
+}
+
+// Extracts the `rel="next"` target of a `Link: <url>; rel="next", <url>; rel="last"` header
+fn next_page_url(response: &Response) -> Option<String> {
+    let link = header_value(response, "Link")?;
+    link.split(',').find_map(|part| {
+        let (url, params) = part.split_once(';')?;
+        params.split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| url.trim().trim_start_matches('<').trim_end_matches('>').to_string())
+    })
+}
+
+impl GithubClient {
+    /// Builds the client of `account`, every request it sends carries the account token
+    pub fn new(config: &AppConfig, account: AccountConfig) -> Result<GithubClient, GithubError> {
+        let mut token = HeaderValue::from_str(&format!("token {}", account.github_token))
+            .map_err(|e| GithubError::Client(format!("invalid token for account {}: {}", account.name, e)))?;
+        token.set_sensitive(true);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(AUTHORIZATION, token);
+        headers.insert(ACCEPT, HeaderValue::from_static("application/vnd.github+json"));
+        headers.insert(USER_AGENT, HeaderValue::from_static("my-rust-app"));
+
+        let http = reqwest::Client::builder()
+            .default_headers(headers)
+            .connect_timeout(CONNECT_TIMEOUT)
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .map_err(|e| GithubError::Client(e.to_string()))?;
+
+        Ok(GithubClient {
+            http,
+            account,
+            page_size: config.page_size,
+            max_pages: config.max_pages,
+        })
+    }
+
+    pub fn account(&self) -> &AccountConfig {
+        &self.account
+    }
+
+    fn get(&self, url: &str) -> RequestBuilder {
+        self.http.get(url)
+    }
+
+    // Follows GitHub's polling contract: the request is conditional on `last_modified`, a 304 answer
+    // means nothing changed and is not parsed, and `X-Poll-Interval` tells how long to wait.
+    // Pages are followed through the `Link` header until there is no next page or `max_pages` is hit.
+    pub async fn fetch_notifications(
+        &self,
+        since: Option<time::OffsetDateTime>,
+        last_modified: Option<&str>,
+    ) -> Result<NotificationsPoll, GithubError> {
+        let url = format!("{}/notifications", self.account.api_url);
+        let mut request = self.get(&url)
+            .query(&[("per_page", self.page_size.to_string())]);
+
+        if let Some(since) = since {
+            let since = since.format(&time::format_description::well_known::Rfc3339).unwrap_or_else(|_| String::new());
+            request = request.query(&[("since", since)]);
         }
-        Err(Error::StatusCode(code)) => {
-            eprintln!("GitHub returned status: {} ", code);
-            notify_error("GitHub API returned error", format!("There was an error when sending request to Github: {:?}", code));
-            default
+
+        if let Some(last_modified) = last_modified {
+            request = request.header(IF_MODIFIED_SINCE, last_modified);
         }
-        Err(e) => {
-            eprintln!("Request error: {}", e);
-            notify_error("GitHub API returned error", format!("There was an error when sending request to Github: {:?}", e));
-            default
+
+        let response = check_response(request.send().await)?;
+
+        let new_last_modified = header_value(&response, "Last-Modified");
+        let poll_interval = header_value(&response, "X-Poll-Interval")
+            .and_then(|seconds| seconds.parse::<u64>().ok())
+            .map(Duration::from_secs);
+        let mut limit = rate_limit(&response);
+
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(NotificationsPoll {
+                notifications: None,
+                last_modified: new_last_modified,
+                poll_interval,
+                rate_limit: limit,
+                truncated: false,
+            });
         }
+
+        let mut next_page = next_page_url(&response);
+        let mut notifications: Vec<NotificationDto> = process_response(Ok(response)).await?;
+        let mut pages = 1;
+        let mut truncated = false;
+
+        while let Some(url) = next_page {
+            if pages >= self.max_pages {
+                eprintln!("[{}] Stopping after {} pages of notifications, {} and later pages are not fetched.", self.account.name, pages, url);
+                truncated = true;
+                break;
+            }
+
+            // The next page URL already carries `since` and `per_page`
+            let response = check_response(self.get(&url).send().await)?;
+            next_page = next_page_url(&response);
+            if let Some(page_limit) = rate_limit(&response) {
+                limit = Some(page_limit);
+            }
+            let page: Vec<NotificationDto> = process_response(Ok(response)).await?;
+            notifications.extend(page);
+            pages += 1;
+        }
+
+        Ok(NotificationsPoll {
+            notifications: Some(notifications),
+            last_modified: new_last_modified,
+            poll_interval,
+            rate_limit: limit,
+            truncated,
+        })
     }
-}
 
-fn prepare_headers<T>(req: RequestBuilder<T>) -> RequestBuilder<T> {
-    let config = AppConfig::load();
+    /// Details of the thread subject, `Ok(None)` when the subject has no URL or its type has no details
+    pub async fn fetch_notification_details(&self, subject: &Subject) -> Result<Option<NotificationDetailDto>, GithubError> {
+        let Some(url) = subject.url.as_deref() else {
+            return Ok(None);
+        };
 
-    req
-        .header("Authorization", &format!("token {}", config.github_token))
-        .header("Accept", "application/vnd.github+json")
-        .header("User-Agent", "my-rust-app")
+        let details = match subject.type_field.as_str() {
+            "PullRequest" => NotificationDetailDto::PullRequest(process_response(self.get(url).send().await).await?),
+            "Issue" => NotificationDetailDto::Issue(process_response(self.get(url).send().await).await?),
+            "Release" => NotificationDetailDto::Release(process_response(self.get(url).send().await).await?),
+            "Commit" => NotificationDetailDto::Commit(process_response(self.get(url).send().await).await?),
+            _ => return Ok(None),
+        };
+        Ok(Some(details))
+    }
 
-}
+    pub async fn fetch_issue_comment(&self, url: &str) -> Result<CommentDto, GithubError> {
+        let res = self.get(url).send().await;
+        process_response(res).await
+    }
 
-pub fn fetch_notifications(since: Option<time::OffsetDateTime>) -> Vec<NotificationDto> {
-    let mut request = ureq::get("https://api.github.com/notifications");
-    request = prepare_headers(request);
+    pub async fn mark_notification_as_read(&self, notification_id: &str) -> Result<(), GithubError> {
+        let url = format!("{}/notifications/threads/{}", self.account.api_url, notification_id);
 
-    if let Some(since) = since {
-        let since = since.format(&time::format_description::well_known::Rfc3339).unwrap_or_else(|_| String::new());
-        request = request.query("since", &since);
+        check_response(self.http.patch(&url).send().await)?;
+        eprintln!("Marked notificattion  {} as read", notification_id);
+        Ok(())
     }
 
-    let res = request.call();
+    /// Stops notifications for the thread, like "Mute" on the web UI
+    pub async fn ignore_thread(&self, notification_id: &str) -> Result<(), GithubError> {
+        let url = format!("{}/notifications/threads/{}/subscription", self.account.api_url, notification_id);
 
-    process_response( res, Vec::new())
-}
+        check_response(self.http.put(&url).json(&serde_json::json!({ "ignored": true })).send().await)?;
+        eprintln!("Muted notification thread {}", notification_id);
+        Ok(())
+    }
 
-pub fn fetch_notification_details(url: &str) -> Option<NotificationDetailDto> {
-    let mut request = ureq::get(url);
-    request = prepare_headers(request);
+    /// Removes the subscription, the thread notifies again on mention or review request
+    pub async fn unsubscribe_thread(&self, notification_id: &str) -> Result<(), GithubError> {
+        let url = format!("{}/notifications/threads/{}/subscription", self.account.api_url, notification_id);
 
-    let res = request.call();
-    process_response( res, None)
-}
+        check_response(self.http.delete(&url).send().await)?;
+        eprintln!("Unsubscribed from notification thread {}", notification_id);
+        Ok(())
+    }
 
-pub fn fetch_issue_comment(url: &str) -> Option<CommentDto> {
-    let mut request = ureq::get(url);
-    request = prepare_headers(request);
+    /// Submits an approving review on the pull request at `pull_request_url` (its API URL)
+    pub async fn approve_pull_request(&self, pull_request_url: &str) -> Result<(), GithubError> {
+        let url = format!("{}/reviews", pull_request_url);
 
-    let res = request.call();
-    process_response( res, None)
-}
+        check_response(self.http.post(&url).json(&serde_json::json!({ "event": "APPROVE" })).send().await)?;
+        eprintln!("Approved pull request {}", pull_request_url);
+        Ok(())
+    }
+
+    /// Merges the pull request at `pull_request_url` (its API URL) with the repository default method
+    pub async fn merge_pull_request(&self, pull_request_url: &str) -> Result<(), GithubError> {
+        let url = format!("{}/merge", pull_request_url);
+
+        check_response(self.http.put(&url).json(&serde_json::json!({})).send().await)?;
+        eprintln!("Merged pull request {}", pull_request_url);
+        Ok(())
+    }
 
-pub fn mark_notification_as_read(notification_id: &String) -> bool {
-    let url = format!("https://api.github.com/notifications/threads/{}", notification_id);
+    /// Adds `content` (`+1`, `heart`, ...) to the comment at `comment_url`, the URL `fetch_issue_comment` takes
+    pub async fn add_reaction(&self, comment_url: &str, content: &str) -> Result<(), GithubError> {
+        let url = format!("{}/reactions", comment_url);
 
-    let mut request = ureq::patch(&url);
-    request = prepare_headers(request);
+        check_response(self.http.post(&url).json(&serde_json::json!({ "content": content })).send().await)?;
+        eprintln!("Reacted {} to comment {}", content, comment_url);
+        Ok(())
+    }
 
-    let res = request.send("");
+    /// Comments on issue or pull request `number` of `repository` (`owner/name`)
+    pub async fn post_comment(&self, repository: &str, number: &str, body: &str) -> Result<(), GithubError> {
+        // Pull request conversations are issue comments
+        let url = format!("{}/repos/{}/issues/{}/comments", self.account.api_url, repository, number);
 
-    match res {
-        Ok(response) if response.status().is_success() => {
-            eprintln!("Marked notificattion  {} as read", notification_id);
-            true
+        check_response(self.http.post(&url).json(&serde_json::json!({ "body": body })).send().await)?;
+        eprintln!("Commented on {}#{}", repository, number);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{details, fixture, notification};
+    use wiremock::matchers::{body_json, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    type DetailKind = fn(&NotificationDetailDto) -> bool;
+
+    fn client(api_url: &str) -> GithubClient {
+        GithubClient {
+            http: reqwest::Client::new(),
+            account: AccountConfig {
+                name: String::from("test"),
+                github_token: String::from("test-token"),
+                api_url: api_url.to_string(),
+            },
+            page_size: 50,
+            max_pages: 1,
         }
-        Ok(response) => {
-            eprintln!("Failed to mark notification as read. Status: {}", response.status());
-            false
+    }
+
+    #[test]
+    fn every_subject_type_deserializes() {
+        let cases = [
+            ("issue_author", "Issue", true),
+            ("pull_request_subscribed", "PullRequest", true),
+            ("release", "Release", true),
+            ("commit", "Commit", true),
+            ("discussion", "Discussion", false),
+            ("check_suite", "CheckSuite", false),
+            ("repository_vulnerability_alert", "RepositoryVulnerabilityAlert", false),
+        ];
+        for (name, subject_type, has_url) in cases {
+            let notification = notification(name);
+            assert_eq!(notification.subject.type_field, subject_type, "{}", name);
+            assert_eq!(notification.subject.url.is_some(), has_url, "{}", name);
         }
-        Err(Error::StatusCode(code)) => {
-            eprintln!("GitHub returned status: {} ", code);
-            false
+    }
+
+    #[test]
+    fn a_page_mixing_subject_types_deserializes() {
+        let page = ["issue_author", "discussion", "check_suite", "repository_vulnerability_alert", "release"]
+            .map(|name| fixture("notifications", name))
+            .join(",");
+        let notifications: Vec<NotificationDto> = serde_json::from_str(&format!("[{}]", page)).unwrap();
+        assert_eq!(notifications.len(), 5);
+        assert!(notifications[1].subject.url.is_none());
+        assert!(notifications[1].subject.latest_comment_url.is_none());
+    }
+
+    #[test]
+    fn details_deserialize_per_subject_type() {
+        let pr: PullRequestDetailDto = details("pull_request_open");
+        assert_eq!((pr.state.as_str(), pr.merged, pr.draft), ("open", false, false));
+        assert_eq!(pr.mergeable_state.as_deref(), Some("clean"));
+        assert_eq!((pr.additions, pr.deletions), (Some(100), Some(3)));
+        assert_eq!(pr.labels[0].name, "bug");
+        assert_eq!(pr.user.unwrap().login, "octocat");
+
+        let merged: PullRequestDetailDto = details("pull_request_merged");
+        assert_eq!((merged.state.as_str(), merged.merged), ("closed", true));
+
+        // Issues have no `merged`
+        let issue: IssueDetailDto = details("issue_closed");
+        assert_eq!(issue.state, "closed");
+        assert_eq!(issue.state_reason.as_deref(), Some("completed"));
+        assert_eq!(issue.html_url.as_deref(), Some("https://github.com/octocat/Hello-World/issues/1348"));
+
+        let release: ReleaseDetailDto = details("release");
+        assert_eq!(release.tag_name.as_deref(), Some("v1.0.0"));
+        assert!(!release.prerelease);
+
+        let commit: CommitDetailDto = details("commit");
+        assert_eq!(
+            commit.html_url.as_deref(),
+            Some("https://github.com/octocat/Hello-World/commit/6dcb09b5b57875f334f61aebed695e2e4193db5e")
+        );
+    }
+
+    #[tokio::test]
+    async fn details_are_fetched_by_subject_type() {
+        let server = MockServer::start().await;
+        let client = client(&server.uri());
+        let cases: [(&str, &str, &str, DetailKind); 4] = [
+            ("pull_request_subscribed", "/pulls/1347", "pull_request_open", |d| matches!(d, NotificationDetailDto::PullRequest(_))),
+            ("issue_author", "/issues/1348", "issue_open", |d| matches!(d, NotificationDetailDto::Issue(_))),
+            ("release", "/releases/1", "release", |d| matches!(d, NotificationDetailDto::Release(_))),
+            ("commit", "/commits/6dcb09b5b57875f334f61aebed695e2e4193db5e", "commit", |d| matches!(d, NotificationDetailDto::Commit(_))),
+        ];
+        for (name, subject_path, details, expected) in cases {
+            let mut notification = notification(name);
+            notification.subject.url = Some(format!("{}/repos/octocat/Hello-World{}", server.uri(), subject_path));
+            Mock::given(method("GET"))
+                .and(path(format!("/repos/octocat/Hello-World{}", subject_path)))
+                .respond_with(ResponseTemplate::new(200).set_body_string(fixture("details", details)))
+                .mount(&server)
+                .await;
+
+            let fetched = client.fetch_notification_details(&notification.subject).await.unwrap();
+            assert!(fetched.as_ref().is_some_and(expected), "{}", name);
         }
-        Err(e) => {
-            eprintln!("Request error: {}", e);
-            false
+    }
+
+    #[tokio::test]
+    async fn subjects_without_url_have_no_details() {
+        // Nothing listens there, a request would fail
+        let client = client("http://127.0.0.1:9");
+        for name in ["discussion", "check_suite", "repository_vulnerability_alert"] {
+            let notification = notification(name);
+            assert!(client.fetch_notification_details(&notification.subject).await.unwrap().is_none(), "{}", name);
         }
     }
-}
\ No newline at end of file
+
+    #[tokio::test]
+    async fn comments_go_to_the_issue_of_the_subject_number() {
+        let server = MockServer::start().await;
+        let client = client(&server.uri());
+        // A repository named `pulls` must not confuse the endpoint
+        let mut notification = notification("pull_request_subscribed");
+        notification.repository.full_name = String::from("octocat/pulls");
+        notification.subject.url = Some(format!("{}/repos/octocat/pulls/pulls/3", server.uri()));
+        Mock::given(method("POST"))
+            .and(path("/repos/octocat/pulls/issues/3/comments"))
+            .and(body_json(serde_json::json!({ "body": "Looks good" })))
+            .respond_with(ResponseTemplate::new(201))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let number = notification.subject.number().unwrap();
+        assert_eq!(number, "3");
+        client.post_comment(&notification.repository.full_name, number, "Looks good").await.unwrap();
+    }
+}
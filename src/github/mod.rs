@@ -1 +1,2 @@
-pub mod github;
\ No newline at end of file
+pub mod github;
+pub mod error;
\ No newline at end of file
@@ -4,34 +4,36 @@ mod github;
 mod notify;
 mod app_config;
 mod utils;
+// Recorded GitHub API responses in `tests/fixtures`, shared by the unit tests
+#[cfg(test)]
+mod fixtures;
 
 use std::fs::{self, File};
 use std::io::{self, Read, Write};
 use std::path::PathBuf;
 use std::sync::{mpsc, Arc, Mutex};
 use std::time::Duration;
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{DateTime, Utc};
 use tokio::sync::mpsc::unbounded_channel;
 use tokio_util::sync::CancellationToken;
-use crate::github::github::fetch_notifications;
 use crate::notify::notify::github_notification;
 use notify::tray::{GuiMessage, SnoozeMessage, Tray};
 use crate::notify::notification_manager::{NotificationManager, NotificationManagerMessage};
+use crate::notify::notification_cache::{NotificationCache, CACHE_RETENTION};
 use crate::notify::snooze_config_store::SnoozeConfigStore;
+use crate::app_config::{AppConfig, FileTokenProvider, SecretServiceTokenProvider, DEFAULT_ACCOUNT_NAME, DEFAULT_ASSETS_DIR};
 
-const INTERVAL_SECONDS: u64 = 60;
-const INTERVAL_TO_NEXT_NOTIFICATION_SECONDS: u64 = 12;
 const LAST_CHECK_FILE_NAME: &str = "last_check";
-const CONFIG_DIR_NAME: &str = ".config/github-notifier";
-const API_URL: &str = "https://api.github.com/notifications";
 
-const DB_FILE_NAME: &str = "config.db";
-
-fn ensure_config_dir() -> io::Result<PathBuf> {
-    let home_dir = dirs::home_dir().expect("Failed to get home directory");
-    let config_dir = home_dir.join(CONFIG_DIR_NAME);
+/// Creates the config dir and returns the last check file of `account`
+fn ensure_config_dir(account: &str) -> io::Result<PathBuf> {
+    let config_dir = AppConfig::config_dir();
     fs::create_dir_all(&config_dir)?;
-    Ok(config_dir.join(LAST_CHECK_FILE_NAME))
+    if account == DEFAULT_ACCOUNT_NAME {
+        Ok(config_dir.join(LAST_CHECK_FILE_NAME))
+    } else {
+        Ok(config_dir.join(format!("{}_{}", LAST_CHECK_FILE_NAME, account)))
+    }
 }
 
 fn save_last_check_time(path: &PathBuf, offset_time: time::OffsetDateTime) -> io::Result<()> {
@@ -74,15 +76,69 @@ enum Message {
     Snooze,
 }
 
-#[tokio::main(flavor = "multi_thread", worker_threads = 4)]
-async fn main() -> io::Result<()> {
-    let last_check_time_file = ensure_config_dir()?;
-    let saved_since =  load_last_check_time(&last_check_time_file).unwrap_or_else(|_| Utc.timestamp_opt(0, 0).unwrap());;
+const USAGE: &str = "Usage:
+    github-notifier
+    github-notifier auth set-token [--account <name>] [--store secret-service|file]
+
+The token is read from stdin, e.g. `printenv GITHUB_TOKEN | github-notifier auth set-token`.";
+
+/// `auth set-token`: stores a token so it never has to be put in the autostart entry
+fn set_token_command(args: &[String]) -> io::Result<()> {
+    let mut account = String::from(DEFAULT_ACCOUNT_NAME);
+    let mut store = String::from("secret-service");
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args.next().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, USAGE))?;
+        match arg.as_str() {
+            "--account" => account = value.clone(),
+            "--store" => store = value.clone(),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+        }
+    }
+
+    eprint!("GitHub token for account {}: ", account);
+    io::stderr().flush()?;
+    let mut token = String::new();
+    io::stdin().read_line(&mut token)?;
+    let token = token.trim();
+    if token.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "No token given"));
+    }
+
+    let stored = match store.as_str() {
+        "secret-service" => SecretServiceTokenProvider.store_token(&account, token),
+        "file" => FileTokenProvider { path: FileTokenProvider::default_path(&account) }.store_token(token),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    };
+    stored.map_err(io::Error::other)?;
 
+    eprintln!("Token for account {} stored in {}.", account, store);
+    Ok(())
+}
 
+fn run_command(args: &[String]) -> io::Result<()> {
+    match args {
+        [group, command, rest @ ..] if group == "auth" && command == "set-token" => set_token_command(rest),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, USAGE)),
+    }
+}
 
-    let mut since =  to_offset_date_time(saved_since).ok();
-    
+#[tokio::main(flavor = "multi_thread", worker_threads = 4)]
+async fn main() -> io::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if !args.is_empty() {
+        return run_command(&args);
+    }
+
+    let config = match AppConfig::load() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            eprintln!("{}", e);
+            AppConfig::notify_config_issue(std::path::Path::new(DEFAULT_ASSETS_DIR), &e.to_string());
+            return Err(io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+    };
 
     let (tx, rx) = mpsc::sync_channel::<Message>(2);
 
@@ -97,19 +153,26 @@ async fn main() -> io::Result<()> {
     let mt_notification_recv = Arc::new(Mutex::new(notification_recv));
     let mt_notification_send = Arc::new(Mutex::new(notification_send));
 
-    let mut store = Arc::new(SnoozeConfigStore::open_default().unwrap());
+    let mut store = Arc::new(SnoozeConfigStore::open_default(&config).unwrap());
+    let cache = Arc::new(NotificationCache::open_default(&config).unwrap());
+    match cache.prune(config.poll_mode, CACHE_RETENTION) {
+        Ok(0) => {}
+        Ok(pruned) => eprintln!("Removed {} stale threads from the notification cache.", pruned),
+        Err(e) => eprintln!("Failed to prune the notification cache: {}", e),
+    }
     // let (gui_tx, gui_rx) = MainContext::channel(gtk::glib::Priority::default());
-    let mut notifications_manager = NotificationManager::new(cancellation_token.clone(), mt_notification_send,mt_gui_recv.clone(), store.clone());
-    let tray = Tray::new(cancellation_token.clone(), mt_gui_recv, mt_snooze_send, store.clone());
+    let notifications_manager = Arc::new(NotificationManager::new(config.clone(), cancellation_token.clone(), mt_notification_send,mt_gui_recv.clone(), Arc::new(Mutex::new(gui_send.clone())), store.clone(), cache));
+    let snooze_manager = notifications_manager.clone();
+    tokio::spawn(async move { snooze_manager.process_snooze_messages(snooze_recv).await });
+    let tray = Tray::new(config.clone(), cancellation_token.clone(), mt_gui_recv, mt_snooze_send, store.clone());
     let trayHandle = tray.run();
 
 
     eprintln!("Starting polling Github notifications.");
-    eprintln!("Listening for notifications since: {}", since.unwrap());
 
 
     gui_send.send(GuiMessage::Quit);
-    notifications_manager.run();
+    notifications_manager.run().await;
     Ok(())
 
 
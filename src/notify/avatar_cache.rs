@@ -0,0 +1,121 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use image::imageops::{self, FilterType};
+use crate::app_config::AppConfig;
+
+const AVATARS_DIR_NAME: &str = "avatars";
+/// Side of the popup image, in pixels
+const AVATAR_SIZE: u32 = 96;
+/// Side of the state badge drawn in the bottom right corner
+const BADGE_SIZE: u32 = 40;
+
+/// Avatars are public, they are fetched without the account token so it never leaves the API host
+fn http() -> &'static reqwest::Client {
+    static HTTP: OnceLock<reqwest::Client> = OnceLock::new();
+    HTTP.get_or_init(|| {
+        reqwest::Client::builder()
+            .user_agent("my-rust-app")
+            .connect_timeout(Duration::from_secs(10))
+            .timeout(Duration::from_secs(30))
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// Author avatars downloaded to `~/.config/github-notifier/avatars` and kept for `avatars.ttl`
+#[derive(Clone, Debug)]
+pub struct AvatarCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl AvatarCache {
+    pub fn open_default(config: &AppConfig) -> std::io::Result<Self> {
+        let dir = AppConfig::config_dir().join(AVATARS_DIR_NAME);
+        fs::create_dir_all(&dir)?;
+        Ok(Self { dir, ttl: config.avatars.ttl })
+    }
+
+    /// Popup image for `login`: the avatar, with `badge` (an asset path) composited on top.
+    /// `None` when the avatar can neither be downloaded nor found in the cache.
+    pub async fn popup_image(&self, login: &str, avatar_url: &str, badge: Option<&Path>) -> Option<PathBuf> {
+        let avatar = self.avatar(login, avatar_url).await?;
+
+        let badge_name = badge.and_then(|badge| badge.file_stem()).and_then(|stem| stem.to_str()).unwrap_or("plain");
+        let target = self.dir.join(format!("{}-{}.png", file_safe(login), badge_name));
+        let badge = badge.map(Path::to_path_buf);
+        let composed = target.clone();
+
+        let error = match tokio::task::spawn_blocking(move || compose(&avatar, badge.as_deref(), &composed)).await {
+            Ok(Ok(())) => return Some(target),
+            Ok(Err(e)) => e.to_string(),
+            Err(e) => e.to_string(),
+        };
+        eprintln!("Failed to compose avatar of {}: {}", login, error);
+        None
+    }
+
+    // Cached avatar file, downloaded again once older than the ttl. A stale file beats no avatar.
+    async fn avatar(&self, login: &str, avatar_url: &str) -> Option<PathBuf> {
+        let path = self.dir.join(format!("{}.img", file_safe(login)));
+        let age = fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+
+        if matches!(age, Some(age) if age < self.ttl) {
+            return Some(path);
+        }
+
+        match download(avatar_url).await {
+            Ok(bytes) => match fs::write(&path, bytes) {
+                Ok(()) => Some(path),
+                Err(e) => {
+                    eprintln!("Failed to cache avatar of {}: {}", login, e);
+                    None
+                }
+            },
+            Err(e) => {
+                eprintln!("Failed to download avatar of {}: {}", login, e);
+                age.map(|_| path)
+            }
+        }
+    }
+}
+
+async fn download(url: &str) -> Result<Vec<u8>, reqwest::Error> {
+    let response = http()
+        .get(url)
+        .query(&[("s", (AVATAR_SIZE * 2).to_string())])
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+fn compose(avatar: &Path, badge: Option<&Path>, target: &Path) -> image::ImageResult<()> {
+    // Avatars are PNG or JPEG depending on the host, the cached file has no telling extension
+    let mut image = image::ImageReader::open(avatar)?
+        .with_guessed_format()?
+        .decode()?
+        .resize_to_fill(AVATAR_SIZE, AVATAR_SIZE, FilterType::Lanczos3)
+        .into_rgba8();
+
+    if let Some(badge) = badge {
+        let badge = image::open(badge)?
+            .resize(BADGE_SIZE, BADGE_SIZE, FilterType::Lanczos3)
+            .into_rgba8();
+        let x = (AVATAR_SIZE - badge.width()) as i64;
+        let y = (AVATAR_SIZE - badge.height()) as i64;
+        imageops::overlay(&mut image, &badge, x, y);
+    }
+
+    image.save(target)
+}
+
+// Logins are alphanumeric with dashes, but the API is not trusted with file names
+fn file_safe(login: &str) -> String {
+    login.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect()
+}
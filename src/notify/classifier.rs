@@ -0,0 +1,97 @@
+use crate::github::github::{NotificationDetailDto, NotificationDto};
+use crate::notify::notify::NotificationType;
+
+/// Maps a thread to the event it most likely reports.
+/// The notifications API only gives the `reason` and the subject, so the reason wins when it is
+/// specific (mention, review request, assignment) and the current subject state is used otherwise.
+pub fn classify(notification: &NotificationDto, details: Option<&NotificationDetailDto>) -> NotificationType {
+    let subject_type = notification.subject.type_field.as_str();
+
+    match notification.reason.as_str() {
+        "mention" | "team_mention" => return NotificationType::Mentions,
+        "review_requested" => return NotificationType::PullRequestReviewRequested,
+        "assign" => return NotificationType::IssueAssigned,
+        _ => {}
+    }
+
+    // A latest comment other than the subject itself means the last activity was a comment
+    let commented = matches!(
+        &notification.subject.latest_comment_url,
+        Some(comment_url) if Some(comment_url) != notification.subject.url.as_ref()
+    );
+    if commented && (subject_type == "Issue" || subject_type == "PullRequest") {
+        return NotificationType::IssueCommented;
+    }
+
+    match details {
+        Some(NotificationDetailDto::PullRequest(pr)) => match (pr.state.as_str(), pr.merged) {
+            ("closed", true) => NotificationType::PullRequestMerged,
+            ("closed", false) => NotificationType::PullRequestClosed,
+            _ => NotificationType::PullRequestOpened,
+        },
+        Some(NotificationDetailDto::Issue(issue)) => match issue.state.as_str() {
+            "closed" => NotificationType::IssueClosed,
+            _ => NotificationType::IssueOpened,
+        },
+        _ => NotificationType::Other,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures::{details, notification};
+
+    fn pull_request(name: &str) -> NotificationDetailDto {
+        NotificationDetailDto::PullRequest(details(name))
+    }
+
+    fn issue(name: &str) -> NotificationDetailDto {
+        NotificationDetailDto::Issue(details(name))
+    }
+
+    fn release(name: &str) -> NotificationDetailDto {
+        NotificationDetailDto::Release(details(name))
+    }
+
+    #[test]
+    fn specific_reasons_win_over_the_subject_state() {
+        let merged = pull_request("pull_request_merged");
+        assert_eq!(classify(&notification("pull_request_mention"), Some(&merged)), NotificationType::Mentions);
+        assert_eq!(classify(&notification("discussion"), None), NotificationType::Mentions);
+        assert_eq!(
+            classify(&notification("pull_request_review_requested"), Some(&pull_request("pull_request_open"))),
+            NotificationType::PullRequestReviewRequested
+        );
+        assert_eq!(classify(&notification("issue_assign"), Some(&issue("issue_closed"))), NotificationType::IssueAssigned);
+    }
+
+    #[test]
+    fn a_latest_comment_other_than_the_subject_is_a_comment() {
+        let open = pull_request("pull_request_open");
+        assert_eq!(classify(&notification("pull_request_comment"), Some(&open)), NotificationType::IssueCommented);
+        // The subject itself as latest comment is not a comment
+        assert_eq!(classify(&notification("pull_request_subscribed"), Some(&open)), NotificationType::PullRequestOpened);
+    }
+
+    #[test]
+    fn pull_requests_are_classified_by_state() {
+        let subscribed = notification("pull_request_subscribed");
+        assert_eq!(classify(&subscribed, Some(&pull_request("pull_request_open"))), NotificationType::PullRequestOpened);
+        assert_eq!(classify(&subscribed, Some(&pull_request("pull_request_closed"))), NotificationType::PullRequestClosed);
+        assert_eq!(classify(&subscribed, Some(&pull_request("pull_request_merged"))), NotificationType::PullRequestMerged);
+    }
+
+    #[test]
+    fn issues_are_classified_by_state() {
+        let authored = notification("issue_author");
+        assert_eq!(classify(&authored, Some(&issue("issue_open"))), NotificationType::IssueOpened);
+        assert_eq!(classify(&authored, Some(&issue("issue_closed"))), NotificationType::IssueClosed);
+    }
+
+    #[test]
+    fn other_subjects_and_missing_details_are_other() {
+        assert_eq!(classify(&notification("release"), Some(&release("release"))), NotificationType::Other);
+        assert_eq!(classify(&notification("pull_request_subscribed"), None), NotificationType::Other);
+    }
+}
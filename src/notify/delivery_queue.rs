@@ -0,0 +1,254 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BTreeMap, BinaryHeap};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+use chrono::Utc;
+use tokio::time::Instant;
+use tokio_util::sync::CancellationToken;
+use crate::app_config::AppConfig;
+use crate::github::github::{GithubClient, NotificationDto};
+use crate::notify::notification_cache::NotificationCache;
+use crate::notify::notify::{digest_notification, github_notification, grouped_notification, PopupContext};
+use crate::notify::snooze_config_store::SnoozeConfigStore;
+
+/// How often held popups check whether the quiet period is over, do not disturb can end early from the tray
+const QUIET_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Popup waiting in the delivery queue
+#[derive(Debug)]
+pub enum Delivery {
+    Single(Box<NotificationDto>),
+    /// Threads of one poll coalesced by `group_by`
+    Group(Vec<NotificationDto>),
+    Digest(Vec<NotificationDto>),
+}
+
+impl Delivery {
+    fn notifications(&self) -> &[NotificationDto] {
+        match self {
+            Delivery::Single(notification) => std::slice::from_ref(notification.as_ref()),
+            Delivery::Group(notifications) | Delivery::Digest(notifications) => notifications,
+        }
+    }
+
+    fn into_notifications(self) -> Vec<NotificationDto> {
+        match self {
+            Delivery::Single(notification) => vec![*notification],
+            Delivery::Group(notifications) | Delivery::Digest(notifications) => notifications,
+        }
+    }
+}
+
+/// Runs once the popups pushed before it for the same account went out, see `DeliveryQueue::push_checkpoint`
+type Checkpoint = Box<dyn FnOnce() + Send>;
+
+enum Message {
+    Popup(GithubClient, Delivery),
+    Checkpoint(String, Checkpoint),
+}
+
+/// Whether popups are held back now, by `quiet_hours` or by do not disturb from the tray
+fn is_quiet(config: &AppConfig, store: &SnoozeConfigStore) -> bool {
+    if config.quiet_hours.is_quiet(Utc::now()) {
+        return true;
+    }
+    match store.do_not_disturb_until(glib::DateTime::now_utc().unwrap()) {
+        Ok(until) => until.is_some(),
+        Err(e) => {
+            eprintln!("Failed to read do not disturb: {}", e);
+            false
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Queued {
+    /// Rank of the most important reason, lower goes first
+    rank: usize,
+    /// Arrival order, keeps popups of the same rank first in, first out
+    seq: u64,
+    client: GithubClient,
+    delivery: Delivery,
+}
+
+impl Queued {
+    fn key(&self) -> (Reverse<usize>, Reverse<u64>) {
+        (Reverse(self.rank), Reverse(self.seq))
+    }
+}
+
+impl PartialEq for Queued {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for Queued {}
+
+impl PartialOrd for Queued {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Queued {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// Paces popups in the background so polling never waits for them.
+/// `delivery.burst` popups go out at once, then one every `notification_interval`,
+/// the most important reasons first. Waiting popups are dropped on cancellation, their threads
+/// are not recorded as shown yet and come back after a restart.
+/// During quiet hours and do not disturb popups are held, then shown as one digest per account.
+#[derive(Debug, Clone)]
+pub struct DeliveryQueue {
+    sender: UnboundedSender<Message>,
+}
+
+impl DeliveryQueue {
+    /// Starts the task draining the queue, it stops with `cancellation_token`.
+    /// Its popups get the queue in their context, so the ones they show again are paced and held too.
+    pub fn start(
+        config: Arc<AppConfig>,
+        store: Arc<SnoozeConfigStore>,
+        cache: Arc<NotificationCache>,
+        cancellation_token: Arc<CancellationToken>,
+    ) -> DeliveryQueue {
+        let (sender, receiver) = unbounded_channel();
+        let queue = DeliveryQueue { sender };
+        let context = PopupContext {
+            config,
+            store,
+            cache,
+            queue: queue.clone(),
+            cancellation_token: cancellation_token.clone(),
+        };
+        tokio::spawn(async move { Self::drain(context, cancellation_token, receiver).await });
+        queue
+    }
+
+    pub fn push(&self, client: GithubClient, delivery: Delivery) {
+        if self.sender.send(Message::Popup(client, delivery)).is_err() {
+            eprintln!("Delivery queue is closed, dropping popup.");
+        }
+    }
+
+    /// Runs `checkpoint` once every popup of `account` pushed so far went out, including held ones.
+    /// It never runs when the app quits first.
+    pub fn push_checkpoint(&self, account: &str, checkpoint: impl FnOnce() + Send + 'static) {
+        let _ = self.sender.send(Message::Checkpoint(account.to_string(), Box::new(checkpoint)));
+    }
+
+    async fn drain(
+        context: PopupContext,
+        cancellation_token: Arc<CancellationToken>,
+        mut receiver: UnboundedReceiver<Message>,
+    ) {
+        let config = context.config.clone();
+        let burst = config.delivery.burst as f64;
+        let interval = config.notification_interval.max(Duration::from_millis(1));
+        let mut tokens = burst;
+        let mut refilled_at = Instant::now();
+        let mut pending = BinaryHeap::new();
+        let mut seq = 0;
+        let mut closed = false;
+        // Held threads per account, in arrival order, with the arrival of the first one
+        let mut held: BTreeMap<String, (GithubClient, u64, Vec<NotificationDto>)> = BTreeMap::new();
+        let mut checkpoints: Vec<(String, u64, Checkpoint)> = Vec::new();
+
+        loop {
+            if is_quiet(&config, &context.store) {
+                for queued in pending.drain() {
+                    let Queued { seq, client, delivery, .. } = queued;
+                    let (_, first_seq, notifications) = held.entry(client.account().name.clone())
+                        .or_insert_with(|| (client, seq, Vec::new()));
+                    *first_seq = (*first_seq).min(seq);
+                    notifications.extend(delivery.into_notifications());
+                }
+            } else if !held.is_empty() {
+                for (account, (client, first_seq, notifications)) in std::mem::take(&mut held) {
+                    eprintln!("[{}] Quiet period over, showing {} held notifications.", account, notifications.len());
+                    let rank = notifications.iter()
+                        .map(|n| config.delivery.rank(&n.reason))
+                        .min()
+                        .unwrap_or(usize::MAX);
+                    // Keeps the place of the held popups, checkpoints pushed after them wait for the digest
+                    pending.push(Queued { rank, seq: first_seq, client, delivery: Delivery::Digest(notifications) });
+                }
+            }
+
+            let (due, waiting): (Vec<_>, Vec<_>) = std::mem::take(&mut checkpoints).into_iter()
+                .partition(|(account, checkpoint_seq, _)| {
+                    let earlier = |popup_account: &str, popup_seq: u64| popup_account == account && popup_seq < *checkpoint_seq;
+                    !pending.iter().any(|queued: &Queued| earlier(&queued.client.account().name, queued.seq))
+                        && !held.iter().any(|(popup_account, (_, first_seq, _))| earlier(popup_account, *first_seq))
+                });
+            checkpoints = waiting;
+            for (_, _, checkpoint) in due {
+                checkpoint();
+            }
+
+            // Token bucket: one token per interval, at most `burst` saved up
+            let now = Instant::now();
+            tokens = (tokens + (now - refilled_at).as_secs_f64() / interval.as_secs_f64()).min(burst);
+            refilled_at = now;
+
+            if tokens >= 1.0 {
+                if let Some(queued) = pending.pop() {
+                    tokens -= 1.0;
+                    Self::deliver(context.clone(), queued);
+                    continue;
+                }
+            }
+
+            if closed && pending.is_empty() && held.is_empty() {
+                break;
+            }
+
+            let wait = if !pending.is_empty() {
+                Some(interval.mul_f64(1.0 - tokens))
+            } else {
+                (!held.is_empty()).then_some(QUIET_CHECK_INTERVAL)
+            };
+
+            tokio::select! {
+                _ = cancellation_token.cancelled() => {
+                    if !pending.is_empty() || !held.is_empty() {
+                        let held_count: usize = held.values().map(|(_, _, notifications)| notifications.len()).sum();
+                        eprintln!("Dropping {} queued popups and {} held notifications on shutdown, they are shown after a restart.", pending.len(), held_count);
+                    }
+                    break;
+                }
+                received = receiver.recv(), if !closed => match received {
+                    Some(Message::Popup(client, delivery)) => {
+                        let rank = delivery.notifications().iter()
+                            .map(|n| config.delivery.rank(&n.reason))
+                            .min()
+                            .unwrap_or(usize::MAX);
+                        pending.push(Queued { rank, seq, client, delivery });
+                        seq += 1;
+                    }
+                    Some(Message::Checkpoint(account, checkpoint)) => {
+                        checkpoints.push((account, seq, checkpoint));
+                        seq += 1;
+                    }
+                    None if pending.is_empty() && held.is_empty() => break,
+                    None => closed = true,
+                },
+                _ = tokio::time::sleep(wait.unwrap_or_default()), if wait.is_some() => {}
+            }
+        }
+    }
+
+    fn deliver(context: PopupContext, queued: Queued) {
+        let client = queued.client;
+        match queued.delivery {
+            Delivery::Single(notification) => tokio::spawn(async move { github_notification(context, client, *notification).await }),
+            Delivery::Group(notifications) => tokio::spawn(async move { grouped_notification(context, client, notifications).await }),
+            Delivery::Digest(notifications) => tokio::spawn(async move { digest_notification(context, client, notifications).await }),
+        };
+    }
+}
@@ -0,0 +1,25 @@
+use std::sync::OnceLock;
+use crate::github::github::NotificationDto;
+
+/// Action id the freedesktop notification spec reserves for the reply box
+pub const REPLY_ACTION: &str = "inline-reply";
+/// Reasons whose popups offer a reply box
+const REPLY_REASONS: [&str; 2] = ["mention", "comment"];
+
+/// Whether the notification server has a reply box (KDE Plasma does), asked once per run
+pub fn inline_reply_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        notify_rust::get_capabilities()
+            .map(|capabilities| capabilities.iter().any(|c| c == "inline-reply"))
+            .unwrap_or(false)
+    })
+}
+
+/// Whether a reply box fits the thread: a mention or comment on an issue or a pull request
+pub fn offered(notification: &NotificationDto) -> bool {
+    REPLY_REASONS.contains(&notification.reason.as_str())
+        && matches!(notification.subject.type_field.as_str(), "Issue" | "PullRequest")
+        && notification.subject.url.is_some()
+        && inline_reply_supported()
+}
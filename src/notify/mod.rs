@@ -1,5 +1,13 @@
 pub mod notify;
 mod snooze_notifications;
+pub mod notification_cache;
+pub mod delivery_queue;
+pub mod classifier;
+pub mod popup_body;
+pub mod inline_reply;
+pub mod popup_events;
+pub mod quick_actions;
+pub mod avatar_cache;
 pub mod tray;
 pub mod notification_manager;
 pub mod snooze_config_store;
\ No newline at end of file
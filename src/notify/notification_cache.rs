@@ -0,0 +1,176 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use chrono::{DateTime, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use crate::app_config::{AppConfig, PollMode};
+use crate::github::github::NotificationDto;
+
+/// Threads not fetched for this long are forgotten in since-last-check mode, they are unread ones nobody touched
+pub const CACHE_RETENTION: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// Notifications fetched from GitHub, kept in the same DB as the snooze settings.
+/// A thread is shown again only when GitHub reports a newer `updated_at` than the one last shown.
+#[derive(Clone, Debug)]
+pub struct NotificationCache {
+    path: PathBuf,
+}
+
+impl NotificationCache {
+
+    /// Open (and create) the cache in the configured DB, ~/.config/github-notifier/config.db by default
+    pub fn open_default(config: &AppConfig) -> rusqlite::Result<Self> {
+        if let Some(dir) = config.db_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        }
+        Self::open_at(&config.db_path)
+    }
+
+    /// Open at a specific path (useful for tests)
+    pub fn open_at<P: AsRef<Path>>(path: P) -> rusqlite::Result<Self> {
+        let conn = Connection::open(path.as_ref())?;
+        Self::migrate(&conn)?;
+        Ok(Self { path: path.as_ref().to_path_buf() })
+    }
+
+    fn connect(&self) -> rusqlite::Result<Connection> {
+        let conn = Connection::open(&self.path)?;
+        conn.pragma_update(None, "journal_mode", "WAL")?;
+        conn.pragma_update(None, "synchronous", "NORMAL")?;
+        Ok(conn)
+    }
+
+    // `user_version` belongs to the snooze store migrations, this table is only ever created
+    fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+        conn.execute_batch(
+            r#"
+            CREATE TABLE IF NOT EXISTS notifications(
+              account    TEXT NOT NULL,
+              thread_id  TEXT NOT NULL,
+              updated_at INTEGER NOT NULL,
+              shown_updated_at INTEGER,
+              repo       TEXT NOT NULL,
+              reason     TEXT NOT NULL,
+              title      TEXT NOT NULL,
+              payload    TEXT NOT NULL,
+              fetched_at INTEGER NOT NULL DEFAULT (unixepoch('now')),
+              UNIQUE(account, thread_id)
+            );
+            "#,
+        )?;
+
+        let has_interacted_at: i64 = conn.query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('notifications') WHERE name='interacted_at'",
+            [],
+            |row| row.get(0),
+        )?;
+        if has_interacted_at == 0 {
+            conn.execute_batch("ALTER TABLE notifications ADD COLUMN interacted_at INTEGER;")?;
+        }
+        Ok(())
+    }
+
+    /// Stores the latest state of a fetched thread, what was shown so far is kept.
+    /// Read threads are never shown, their row is dropped instead.
+    pub fn save(&self, account: &str, notification: &NotificationDto) -> rusqlite::Result<()> {
+        let conn = self.connect()?;
+        if !notification.unread {
+            conn.execute(
+                "DELETE FROM notifications WHERE account=? AND thread_id=?",
+                params![account, notification.id],
+            )?;
+            return Ok(());
+        }
+        let payload = serde_json::to_string(notification)
+            .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+
+        conn.execute(
+            r#"
+            INSERT INTO notifications(account, thread_id, updated_at, repo, reason, title, payload)
+            VALUES(?, ?, ?, ?, ?, ?, ?)
+            ON CONFLICT(account, thread_id) DO UPDATE SET
+              updated_at=excluded.updated_at,
+              repo=excluded.repo,
+              reason=excluded.reason,
+              title=excluded.title,
+              payload=excluded.payload,
+              fetched_at=unixepoch('now')
+            "#,
+            params![
+                account,
+                notification.id,
+                notification.updated_at.timestamp(),
+                notification.repository.full_name,
+                notification.reason,
+                notification.subject.title,
+                payload,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Removes threads not fetched for `max_age`, returns how many. Only since-last-check polls from
+    /// the last check, the other modes keep old unread threads until `prune_unlisted` finds them read.
+    pub fn prune(&self, poll_mode: PollMode, max_age: Duration) -> rusqlite::Result<usize> {
+        if poll_mode != PollMode::SinceLastCheck {
+            return Ok(0);
+        }
+        let conn = self.connect()?;
+        conn.execute(
+            "DELETE FROM notifications WHERE fetched_at < unixepoch('now') - ?",
+            params![max_age.as_secs() as i64],
+        )
+    }
+
+    /// Removes the threads of `account` missing from a complete listing of its unread threads that
+    /// started at `listed_at`, they were read or are gone. Returns how many
+    pub fn prune_unlisted(&self, account: &str, listed_at: DateTime<Utc>) -> rusqlite::Result<usize> {
+        let conn = self.connect()?;
+        conn.execute(
+            "DELETE FROM notifications WHERE account=? AND fetched_at < ?",
+            params![account, listed_at.timestamp()],
+        )
+    }
+
+    /// True when the thread was never shown or changed since it was last shown
+    pub fn is_new_or_updated(&self, account: &str, thread_id: &str, updated_at: DateTime<Utc>) -> rusqlite::Result<bool> {
+        let conn = self.connect()?;
+        let shown: Option<Option<i64>> = conn.query_row(
+            "SELECT shown_updated_at FROM notifications WHERE account=? AND thread_id=?",
+            params![account, thread_id],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(!matches!(shown, Some(Some(shown)) if shown >= updated_at.timestamp()))
+    }
+
+    /// Remember that the thread was shown in the state it had at `updated_at`
+    pub fn mark_shown(&self, account: &str, thread_id: &str, updated_at: DateTime<Utc>) -> rusqlite::Result<()> {
+        let conn = self.connect()?;
+        conn.execute(
+            "UPDATE notifications SET shown_updated_at=? WHERE account=? AND thread_id=?",
+            params![updated_at.timestamp(), account, thread_id],
+        )?;
+        Ok(())
+    }
+
+    /// Remember that a popup action (open, mark as read, dismiss) was used on the thread
+    pub fn mark_interacted(&self, account: &str, thread_id: &str, at: DateTime<Utc>) -> rusqlite::Result<()> {
+        let conn = self.connect()?;
+        conn.execute(
+            "UPDATE notifications SET interacted_at=? WHERE account=? AND thread_id=?",
+            params![at.timestamp(), account, thread_id],
+        )?;
+        Ok(())
+    }
+
+    /// Last popup action used on the thread, `None` if never
+    pub fn last_interaction(&self, account: &str, thread_id: &str) -> rusqlite::Result<Option<DateTime<Utc>>> {
+        let conn = self.connect()?;
+        let at: Option<Option<i64>> = conn.query_row(
+            "SELECT interacted_at FROM notifications WHERE account=? AND thread_id=?",
+            params![account, thread_id],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(at.flatten().and_then(|at| DateTime::from_timestamp(at, 0)))
+    }
+}
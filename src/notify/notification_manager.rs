@@ -1,14 +1,18 @@
-use std::collections::VecDeque;
+use std::collections::HashMap;
 use std::sync::{Arc, Mutex};
-use std::time::Duration;
-use chrono::{TimeZone, Utc};
+use chrono::{DateTime, Local, NaiveTime, TimeZone, Utc};
 use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
-use crate::github::github::fetch_notifications;
-use crate::{ensure_config_dir, load_last_check_time, save_last_check_time, to_offset_date_time, INTERVAL_SECONDS, INTERVAL_TO_NEXT_NOTIFICATION_SECONDS};
-use crate::notify::notify::github_notification;
+use crate::app_config::{AccountConfig, AppConfig, GroupBy, PollMode};
+use crate::github::error::GithubError;
+use crate::github::github::{GithubClient, NotificationDto, RateLimit};
+use crate::{ensure_config_dir, load_last_check_time, save_last_check_time, to_offset_date_time};
+use crate::notify::delivery_queue::{Delivery, DeliveryQueue};
+use crate::notify::notify::notify_github_error;
+use crate::notify::notification_cache::NotificationCache;
 use crate::notify::snooze_config_store::SnoozeConfigStore;
-use crate::notify::tray::GuiMessage;
+use crate::notify::tray::{DoNotDisturb, GuiMessage, RateLimitMenuItemData, SnoozeMessage};
 
 #[derive(Debug)]
 pub struct RepositoryMenuItemData {
@@ -33,117 +37,627 @@ pub enum NotificationManagerMessage {
 }
 #[derive(Debug)]
 pub struct NotificationManager {
+    config: Arc<AppConfig>,
     cancellation_token: Arc<CancellationToken>,
     notification_send: Arc<Mutex<UnboundedSender<NotificationManagerMessage>>>,
     gui_receive: Arc<Mutex<UnboundedReceiver<GuiMessage>>>,
-    store: Arc<SnoozeConfigStore>
+    gui_send: Arc<Mutex<UnboundedSender<GuiMessage>>>,
+    store: Arc<SnoozeConfigStore>,
+    cache: Arc<NotificationCache>,
 }
 
-#[derive(Debug)]
-pub struct SeenNotifications {
+/// Local time at which "Until Tomorrow" do not disturb ends
+const DO_NOT_DISTURB_TOMORROW_AT: NaiveTime = NaiveTime::from_hms_opt(8, 0, 0).unwrap();
 
-    seen_notifications: VecDeque<String>
+/// Transient failures double the poll interval up to 2^6 times, about an hour with the default interval
+const MAX_BACKOFF_EXPONENT: u32 = 6;
 
-}
+impl NotificationManager {
+    pub fn new(
+        config: Arc<AppConfig>,
+        cancellation_token: Arc<CancellationToken>,
+        notification_send: Arc<Mutex<UnboundedSender<NotificationManagerMessage>>>,
+        gui_receive: Arc<Mutex<UnboundedReceiver<GuiMessage>>>,
+        gui_send: Arc<Mutex<UnboundedSender<GuiMessage>>>,
+        store: Arc<SnoozeConfigStore>,
+        cache: Arc<NotificationCache>,
+    ) -> NotificationManager {
+        NotificationManager {
+            config,
+            cancellation_token,
+            notification_send,
+            gui_receive,
+            gui_send,
+            store,
+            cache,
+        }
+    }
+
+    fn report_rate_limit(&self, account: &AccountConfig, rate_limit: &RateLimit) {
+        let _ = self.gui_send.lock().unwrap().send(GuiMessage::UpdateRateLimit(RateLimitMenuItemData {
+            account: account.name.clone(),
+            remaining: rate_limit.remaining,
+            limit: rate_limit.limit,
+            reset: rate_limit.reset,
+        }));
+    }
 
-impl SeenNotifications {
-    pub fn new() -> SeenNotifications {
-        SeenNotifications {
-            seen_notifications: VecDeque::with_capacity(200)
+    fn should_notify(&self, account: &AccountConfig, notification: &NotificationDto, poll_mode: PollMode) -> bool {
+        if !notification.unread {
+            return false;
+        }
+        match poll_mode {
+            PollMode::AllUnread | PollMode::SinceLastCheck => true,
+            PollMode::SinceLastInteraction => {
+                if notification.is_read_since_update() {
+                    eprintln!("[{}] Notification {} was already read on GitHub, skipping.", account.name, notification.id);
+                    return false;
+                }
+                let interacted = self.cache.last_interaction(&account.name, &notification.id).ok().flatten();
+                !matches!(interacted, Some(at) if at >= notification.updated_at)
+            }
         }
     }
 
-    pub fn add(&mut self, notification_id: String) {
-        if self.is_seen(&notification_id){
-            return;
+    /// Applies the tray choices sent over the snooze channel until the app quits
+    pub async fn process_snooze_messages(self: Arc<Self>, mut receiver: UnboundedReceiver<SnoozeMessage>) {
+        loop {
+            let message = tokio::select! {
+                _ = self.cancellation_token.cancelled() => break,
+                message = receiver.recv() => message,
+            };
+            match message {
+                Some(SnoozeMessage::ToggleNotificationType(notification_type)) => {
+                    match self.store.toggle_notification_type(notification_type.as_str()) {
+                        Ok(muted) => eprintln!("Popups for {} are now {}.", notification_type.as_str(), if muted { "muted" } else { "shown" }),
+                        Err(e) => eprintln!("Failed to toggle {}: {}", notification_type.as_str(), e),
+                    }
+                }
+                Some(SnoozeMessage::DoNotDisturb(do_not_disturb)) => self.set_do_not_disturb(do_not_disturb),
+                Some(SnoozeMessage::Quit) | None => break,
+                Some(message) => eprintln!("Unhandled snooze message: {:?}", message),
+            }
         }
+    }
+
+    fn set_do_not_disturb(&self, do_not_disturb: DoNotDisturb) {
+        let until = match do_not_disturb {
+            DoNotDisturb::For(duration) => Some(Utc::now() + duration),
+            DoNotDisturb::UntilTomorrow => {
+                let tomorrow = Local::now().date_naive().succ_opt().map(|day| day.and_time(DO_NOT_DISTURB_TOMORROW_AT));
+                tomorrow.and_then(|at| at.and_local_timezone(Local).earliest()).map(|at| at.with_timezone(&Utc))
+            }
+            DoNotDisturb::Off => None,
+        };
+        let until = until.and_then(|until| glib::DateTime::from_unix_utc(until.timestamp()).ok());
 
-        if(self.seen_notifications.len() >= 200){
-            while(self.seen_notifications.len() >= 200){
-                self.seen_notifications.pop_front();
+        match self.store.set_do_not_disturb(until.clone()) {
+            Ok(()) => {
+                match until.as_ref().and_then(|until| until.to_local().ok()).and_then(|until| until.format("%F %H:%M").ok()) {
+                    Some(until) => eprintln!("Do not disturb until {}.", until),
+                    None => eprintln!("Do not disturb is off."),
+                }
+                let _ = self.gui_send.lock().unwrap().send(GuiMessage::DoNotDisturbChanged);
             }
+            Err(e) => eprintln!("Failed to set do not disturb: {}", e),
         }
-        self.seen_notifications.push_back(notification_id);
     }
 
-    pub fn is_seen(&self, notification_id: &str) -> bool {
-        let found = self.seen_notifications.iter().find(|x| {*x == notification_id});
-        found.is_some()
+    /// Sleeps for `duration`, returning early when the app quits
+    async fn sleep(&self, duration: std::time::Duration) {
+        tokio::select! {
+            _ = self.cancellation_token.cancelled() => {}
+            _ = tokio::time::sleep(duration) => {}
+        }
     }
-}
 
-impl NotificationManager {
-    pub fn new(
-        cancellation_token: Arc<CancellationToken>,
-        notification_send: Arc<Mutex<UnboundedSender<NotificationManagerMessage>>>,
-        gui_receive: Arc<Mutex<UnboundedReceiver<GuiMessage>>>,
-        store: Arc<SnoozeConfigStore>
-    ) -> NotificationManager {
-        NotificationManager {
-            cancellation_token,
-            notification_send,
-            gui_receive,
-            store
+    /// Splits the threads of a poll by `group_by`, keeping the order in which GitHub listed them
+    fn group(&self, notifications: Vec<NotificationDto>) -> Vec<Vec<NotificationDto>> {
+        let mut groups: Vec<(String, Vec<NotificationDto>)> = Vec::new();
+        for n in notifications {
+            let key = match self.config.group_by {
+                GroupBy::None => n.id.clone(),
+                GroupBy::Repository => n.repository.full_name.clone(),
+                // Threads without a subject URL cannot be matched, they stay on their own
+                GroupBy::Subject => n.subject.url.clone().unwrap_or_else(|| n.id.clone()),
+            };
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, group)) => group.push(n),
+                None => groups.push((key, vec![n])),
+            }
         }
+        groups.into_iter().map(|(_, group)| group).collect()
     }
 
-    pub fn run(&mut self) {
-        //TODO: Some central config manager?
-        let last_check_time_file = ensure_config_dir().unwrap();
-        let saved_since =  load_last_check_time(&last_check_time_file).unwrap_or_else(|_| Utc.timestamp_opt(0, 0).unwrap());;
+    /// Threads of a poll that get a popup, in the order GitHub listed them. `queued` holds the threads
+    /// handed to the queue or the digest by their update, the cache only records a popup once it is
+    /// shown, so this keeps later polls from queueing them again.
+    fn select(
+        &self,
+        account: &AccountConfig,
+        poll_mode: PollMode,
+        notifications: Vec<NotificationDto>,
+        queued: &mut HashMap<String, DateTime<Utc>>,
+    ) -> Vec<NotificationDto> {
+        // Once shown the cache skips them, which keeps `queued` to the popups still on their way
+        queued.retain(|id, updated_at| self.cache.is_new_or_updated(&account.name, id, *updated_at).unwrap_or(true));
+
+        let mut to_show = Vec::new();
+        for n in notifications {
+            if let Err(e) = self.cache.save(&account.name, &n) {
+                eprintln!("[{}] Failed to cache notification {}: {}", account.name, n.id, e);
+            }
+            // Without a working cache it is better to repeat a popup than to lose one
+            if !self.cache.is_new_or_updated(&account.name, &n.id, n.updated_at).unwrap_or(true) {
+                continue;
+            }
+            if queued.get(&n.id).is_some_and(|updated_at| *updated_at >= n.updated_at) {
+                continue;
+            }
+            if !self.should_notify(account, &n, poll_mode) {
+                continue;
+            }
+            queued.insert(n.id.clone(), n.updated_at);
+            self.store.add_repo(&account.name, &n.repository.owner.login, &n.repository.full_name);
+            to_show.push(n);
+        }
+        to_show
+    }
 
-        let mut since =  to_offset_date_time(saved_since).ok();
-        let mut tasks = Vec::new();
+    /// `since` of the next poll. Since-last-check moves to the check that just ran. The other modes
+    /// only move up to the newest update processed, never to the local clock: threads are re-shown
+    /// by the cache when they are updated, so asking only for newer activity does not lose anything.
+    /// A `truncated` poll keeps `since`, moving it would skip the threads on the pages it did not fetch.
+    fn next_since(
+        poll_mode: PollMode,
+        since: Option<time::OffsetDateTime>,
+        checked_at: time::OffsetDateTime,
+        processed_until: Option<DateTime<Utc>>,
+        truncated: bool,
+    ) -> Option<time::OffsetDateTime> {
+        if truncated {
+            return since;
+        }
+        match poll_mode {
+            PollMode::SinceLastCheck => Some(checked_at),
+            PollMode::AllUnread | PollMode::SinceLastInteraction => {
+                processed_until.and_then(|processed_until| to_offset_date_time(processed_until).ok()).or(since)
+            }
+        }
+    }
+
+    /// Saves `checked_at` as the last check of `account` once the popups pushed so far went out,
+    /// a restart then resumes from it without losing a popup that was still queued.
+    fn save_check_when_delivered(queue: &DeliveryQueue, account: &AccountConfig, checked_at: time::OffsetDateTime) {
+        let name = account.name.clone();
+        queue.push_checkpoint(&account.name, move || {
+            let saved = ensure_config_dir(&name).and_then(|path| save_last_check_time(&path, checked_at));
+            if let Err(e) = saved {
+                eprintln!("[{}] Failed to save last check time: {}", name, e);
+            }
+        });
+    }
+
+    /// Polls every configured account in its own task until cancelled
+    pub async fn run(self: Arc<Self>) {
+        let mut pollers = tokio::task::JoinSet::new();
+        let queue = DeliveryQueue::start(
+            self.config.clone(),
+            self.store.clone(),
+            self.cache.clone(),
+            self.cancellation_token.clone(),
+        );
+
+        for account in &self.config.accounts {
+            let client = match GithubClient::new(&self.config, account.clone()) {
+                Ok(client) => client,
+                Err(e) => {
+                    eprintln!("[{}] Not polling: {}", account.name, e);
+                    notify_github_error(&self.config, &format!("[{}] GitHub polling failed", account.name), &e);
+                    continue;
+                }
+            };
+            let manager = self.clone();
+            let queue = queue.clone();
+            pollers.spawn(async move { manager.poll_account(client, queue).await });
+        }
+
+        while pollers.join_next().await.is_some() {}
+    }
+
+    fn prune_unlisted(&self, account: &AccountConfig, listed_at: DateTime<Utc>) {
+        match self.cache.prune_unlisted(&account.name, listed_at) {
+            Ok(0) => {}
+            Ok(pruned) => eprintln!("[{}] Removed {} read threads from the notification cache.", account.name, pruned),
+            Err(e) => eprintln!("[{}] Failed to prune the notification cache: {}", account.name, e),
+        }
+    }
+
+    async fn poll_account(&self, client: GithubClient, queue: DeliveryQueue) {
+        let account = client.account();
+        let poll_mode = self.config.poll_mode;
+        let last_check_time_file = ensure_config_dir(&account.name).unwrap();
+
+        // Only since-last-check resumes from the saved check, the other modes need every unread thread once
+        let mut since = match poll_mode {
+            PollMode::SinceLastCheck => {
+                let saved_since = load_last_check_time(&last_check_time_file).unwrap_or_else(|_| Utc.timestamp_opt(0, 0).unwrap());
+                to_offset_date_time(saved_since).ok()
+            }
+            PollMode::AllUnread | PollMode::SinceLastInteraction => None,
+        };
 
-        let mut seen_notifications = SeenNotifications::new();
+        let mut last_modified: Option<String> = None;
+        let mut failures: u32 = 0;
+        let mut last_error: Option<String> = None;
+        let mut digest: Vec<NotificationDto> = Vec::new();
+        let mut queued: HashMap<String, DateTime<Utc>> = HashMap::new();
+        let mut next_digest = self.config.digest.next_after(Local::now());
+        let mut poll_at = Instant::now();
+
+        eprintln!("Polling Github notifications for account {} at {}.", account.name, account.api_url);
 
         loop {
             if self.cancellation_token.is_cancelled(){
                 break;
             }
-            let new_since = to_offset_date_time(Utc::now())
+            if self.config.digest.enabled && Local::now() >= next_digest {
+                if !digest.is_empty() {
+                    queue.push(client.clone(), Delivery::Digest(std::mem::take(&mut digest)));
+                    // Every thread up to the last check is queued now
+                    if let (PollMode::SinceLastCheck, Some(since)) = (poll_mode, since) {
+                        Self::save_check_when_delivered(&queue, account, since);
+                    }
+                }
+                next_digest = self.config.digest.next_after(Local::now());
+            }
+            // Wakes up for the digest in between polls, so its times do not slip by up to a poll interval
+            let until_poll = poll_at.saturating_duration_since(Instant::now());
+            if !until_poll.is_zero() {
+                let wait = if self.config.digest.enabled {
+                    until_poll.min((next_digest - Local::now()).to_std().unwrap_or_default())
+                } else {
+                    until_poll
+                };
+                self.sleep(wait).await;
+                continue;
+            }
+            let polled_at = Utc::now();
+            let new_since = to_offset_date_time(polled_at)
                 .expect("Failed to convert to OffsetDateTime");
 
-            let notifications = fetch_notifications(since);
+            let poll = match client.fetch_notifications(since, last_modified.as_deref()).await {
+                Ok(poll) => poll,
+                Err(GithubError::RateLimited { retry_after, rate_limit }) => {
+                    if let Some(rate_limit) = &rate_limit {
+                        self.report_rate_limit(account, rate_limit);
+                    }
+                    eprintln!("[{}] Rate limit exceeded, suspending polling for {}s.", account.name, retry_after.as_secs());
+                    poll_at = Instant::now() + retry_after.max(self.config.poll_interval);
+                    continue;
+                }
+                Err(e) if e.is_transient() => {
+                    failures += 1;
+                    let backoff = self.config.poll_interval.saturating_mul(2u32.pow(failures.min(MAX_BACKOFF_EXPONENT)));
+                    eprintln!("[{}] Polling failed {} time(s) in a row ({}), retrying in {}s.", account.name, failures, e, backoff.as_secs());
+                    poll_at = Instant::now() + backoff;
+                    continue;
+                }
+                Err(e) => {
+                    eprintln!("[{}] Polling failed: {}", account.name, e);
+                    // Auth and decode errors repeat on every poll, only pop up when the error changes
+                    let message = e.to_string();
+                    if last_error.as_deref() != Some(message.as_str()) {
+                        notify_github_error(&self.config, &format!("[{}] GitHub polling failed", account.name), &e);
+                        last_error = Some(message);
+                    }
+                    poll_at = Instant::now() + self.config.poll_interval;
+                    continue;
+                }
+            };
+            failures = 0;
+            last_error = None;
+
+            if poll.last_modified.is_some() {
+                last_modified = poll.last_modified;
+            }
+            // Never poll more often than GitHub asks us to
+            let poll_interval = poll.poll_interval
+                .map_or(self.config.poll_interval, |interval| interval.max(self.config.poll_interval));
+
+            if let Some(rate_limit) = &poll.rate_limit {
+                self.report_rate_limit(account, rate_limit);
+            }
+
+            // An exhausted quota suspends polling until the reset, even if this request still went through
+            let suspend = poll.rate_limit.as_ref()
+                .filter(|rate_limit| rate_limit.remaining == 0)
+                .and_then(|rate_limit| (rate_limit.reset - Utc::now()).to_std().ok());
 
-            //TODO: Seen notifications is ever growing, for now its fine, but it would be nice to clean it
-            //maybe after notification action is triggered?
+            let next_poll = if let Some(suspend) = suspend {
+                eprintln!("[{}] Rate limit exhausted, suspending polling for {}s.", account.name, suspend.as_secs());
+                suspend.max(poll_interval)
+            } else {
+                poll_interval
+            };
 
+            let notifications = match poll.notifications {
+                Some(notifications) => notifications,
+                None => {
+                    eprintln!("[{}] No changes since {}.", account.name, last_modified.as_deref().unwrap_or("last poll"));
+                    poll_at = Instant::now() + next_poll;
+                    continue;
+                }
+            };
 
+            // Only move `since` up to what was actually processed, never to the local clock
+            let processed_until = notifications.iter().map(|n| n.updated_at).max();
 
-            let tasks_amount = tasks.len();
+            let to_show = self.select(account, poll_mode, notifications, &mut queued);
 
-            let new_tasks: Vec<_> = notifications.into_iter()
-                .filter(|n| !seen_notifications.is_seen(&n.id))
-                .collect::<Vec<_>>()  // Collect first to end the immutable borrow
-                .into_iter()
-                .map(
-                    |n| {
-                        seen_notifications.add(n.id.clone());
-                        self.store.add_repo(&n.repository.owner.login, &n.repository.full_name);
-                        let repos = self.store.list_all_repos();
-                        std::thread::sleep(Duration::from_secs(INTERVAL_TO_NEXT_NOTIFICATION_SECONDS));
-                        tokio::spawn(async move { github_notification(n.clone()).await })
-                    }
-                )
-                .collect();
-            tasks.extend(new_tasks);
+            // Every thread of a complete listing refreshed its row, the rows left behind were read
+            if since.is_none() && !poll.truncated {
+                self.prune_unlisted(account, polled_at);
+            }
 
-            tasks.retain(|handle| !handle.is_finished());
+            if self.config.digest.enabled {
+                digest.extend(to_show);
+            } else {
+                for mut group in self.group(to_show) {
+                    let delivery = if group.len() == 1 {
+                        Delivery::Single(Box::new(group.remove(0)))
+                    } else {
+                        Delivery::Group(group)
+                    };
+                    queue.push(client.clone(), delivery);
+                }
+            }
 
-            eprintln!("There are {} tasks running.", tasks_amount);
-            //Minimize timestamping only to moment when there were actually any notifications present
-            // if tasks_amount > 0 {
-            //     save_last_check_time(&last_check_time_file, new_since);
-            // }
 
-            //save_last_check_time(&last_check_time_file, new_since)?;
-            std::thread::sleep(Duration::from_secs(INTERVAL_SECONDS));
+            // Threads still waiting for the digest or on unfetched pages would be lost, so the saved check stays behind them
+            if poll_mode == PollMode::SinceLastCheck && digest.is_empty() && !poll.truncated {
+                Self::save_check_when_delivered(&queue, account, new_since);
+            }
+            since = Self::next_since(poll_mode, since, new_since, processed_until, poll.truncated);
 
-            //TODO: Original idea was to filter notification posted after last check
-            // however as for now I would like not opened notification to be re-sent
-            // I think in future this behaviour should be configurable.
-            // since = Some(new_since);
+            poll_at = Instant::now() + next_poll;
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::notification_cache::CACHE_RETENTION;
+    use serde_json::{json, Value};
+    use tokio::sync::mpsc::unbounded_channel;
+    use wiremock::matchers::{method, path, query_param, query_param_is_missing};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    struct Fixture {
+        manager: NotificationManager,
+        client: GithubClient,
+        server: MockServer,
+        _dir: tempfile::TempDir,
+    }
+
+    // `settings` are top-level config lines, the account points at the mock server
+    async fn fixture(settings: &str) -> Fixture {
+        let server = MockServer::start().await;
+        let dir = tempfile::tempdir().unwrap();
+        let token_file = dir.path().join("token");
+        std::fs::write(&token_file, "test-token").unwrap();
+        let config = AppConfig::from_toml(&format!(
+            "{}\n[[accounts]]\nname = \"test\"\napi_url = \"{}\"\ntoken_source = \"file\"\ntoken_file = \"{}\"\n",
+            settings,
+            server.uri(),
+            token_file.display(),
+        )).unwrap();
+
+        let client = GithubClient::new(&config, config.accounts[0].clone()).unwrap();
+        let (notification_send, _) = unbounded_channel();
+        let (gui_send, gui_receive) = unbounded_channel();
+        let manager = NotificationManager::new(
+            Arc::new(config),
+            Arc::new(CancellationToken::new()),
+            Arc::new(Mutex::new(notification_send)),
+            Arc::new(Mutex::new(gui_receive)),
+            Arc::new(Mutex::new(gui_send)),
+            Arc::new(SnoozeConfigStore::open_at(dir.path().join("config.db")).unwrap()),
+            Arc::new(NotificationCache::open_at(dir.path().join("config.db")).unwrap()),
+        );
+        Fixture { manager, client, server, _dir: dir }
+    }
+
+    fn thread(id: &str, updated_at: &str, last_read_at: Option<&str>) -> Value {
+        json!({
+            "id": id,
+            "reason": "mention",
+            "repository": { "full_name": "octo/repo", "owner": { "login": "octo" } },
+            "subject": {
+                "title": format!("Thread {}", id),
+                "type": "Issue",
+                "url": format!("https://api.github.com/repos/octo/repo/issues/{}", id),
+                "latest_comment_url": null
+            },
+            "url": format!("https://api.github.com/notifications/threads/{}", id),
+            "unread": true,
+            "updated_at": updated_at,
+            "last_read_at": last_read_at
+        })
+    }
+
+    async fn serve(server: &MockServer, since: Option<&str>, threads: Vec<Value>) {
+        server.reset().await;
+        let mock = Mock::given(method("GET")).and(path("/notifications"));
+        let mock = match since {
+            Some(since) => mock.and(query_param("since", since)),
+            None => mock.and(query_param_is_missing("since")),
+        };
+        mock.respond_with(ResponseTemplate::new(200).set_body_json(Value::Array(threads)))
+            .expect(1)
+            .mount(server)
+            .await;
+    }
+
+    fn at(rfc3339: &str) -> time::OffsetDateTime {
+        to_offset_date_time(DateTime::parse_from_rfc3339(rfc3339).unwrap().with_timezone(&Utc)).unwrap()
+    }
+
+    fn ids(notifications: &[NotificationDto]) -> Vec<&str> {
+        notifications.iter().map(|n| n.id.as_str()).collect()
+    }
+
+    async fn poll(fixture: &Fixture, since: Option<time::OffsetDateTime>, queued: &mut HashMap<String, DateTime<Utc>>) -> (Vec<NotificationDto>, Option<DateTime<Utc>>) {
+        let notifications = fixture.client.fetch_notifications(since, None).await.unwrap().notifications.unwrap();
+        let processed_until = notifications.iter().map(|n| n.updated_at).max();
+        let selected = fixture.manager.select(fixture.client.account(), fixture.manager.config.poll_mode, notifications, queued);
+        (selected, processed_until)
+    }
+
+    #[tokio::test]
+    async fn all_unread_shows_each_update_once_and_asks_from_the_newest_update() {
+        let fixture = fixture("poll_mode = \"all-unread\"").await;
+        let account = fixture.client.account().name.clone();
+        let mut queued = HashMap::new();
+
+        serve(&fixture.server, None, vec![
+            thread("1", "2026-01-01T10:00:00Z", None),
+            thread("2", "2026-01-01T09:00:00Z", Some("2026-01-01T09:30:00Z")),
+        ]).await;
+        let (selected, processed_until) = poll(&fixture, None, &mut queued).await;
+        assert_eq!(ids(&selected), ["1", "2"]);
+        fixture.manager.cache.mark_shown(&account, "2", selected[1].updated_at).unwrap();
+
+        let since = NotificationManager::next_since(PollMode::AllUnread, None, at("2026-01-01T11:00:00Z"), processed_until, false);
+        assert_eq!(since, Some(at("2026-01-01T10:00:00Z")));
+
+        // Thread 1 is still queued, thread 2 was shown, only the update of thread 1 is new
+        serve(&fixture.server, Some("2026-01-01T10:00:00Z"), vec![
+            thread("1", "2026-01-01T10:00:00Z", None),
+            thread("2", "2026-01-01T09:00:00Z", Some("2026-01-01T09:30:00Z")),
+        ]).await;
+        let (selected, _) = poll(&fixture, since, &mut queued).await;
+        assert!(selected.is_empty());
+        // Shown threads leave `queued`, the cache remembers them
+        assert_eq!(queued.keys().collect::<Vec<_>>(), ["1"]);
+
+        serve(&fixture.server, Some("2026-01-01T10:00:00Z"), vec![thread("1", "2026-01-01T12:00:00Z", None)]).await;
+        let (selected, _) = poll(&fixture, since, &mut queued).await;
+        assert_eq!(ids(&selected), ["1"]);
+    }
+
+    #[tokio::test]
+    async fn since_last_check_asks_from_the_last_check() {
+        let fixture = fixture("poll_mode = \"since-last-check\"").await;
+        let mut queued = HashMap::new();
+        let last_check = at("2026-01-01T08:00:00Z");
+
+        serve(&fixture.server, Some("2026-01-01T08:00:00Z"), vec![
+            thread("1", "2026-01-01T10:00:00Z", None),
+            thread("2", "2026-01-01T09:00:00Z", Some("2026-01-01T09:30:00Z")),
+        ]).await;
+        let (selected, processed_until) = poll(&fixture, Some(last_check), &mut queued).await;
+        assert_eq!(ids(&selected), ["1", "2"]);
+
+        let checked_at = at("2026-01-01T11:00:00Z");
+        let since = NotificationManager::next_since(PollMode::SinceLastCheck, Some(last_check), checked_at, processed_until, false);
+        assert_eq!(since, Some(checked_at));
+
+        serve(&fixture.server, Some("2026-01-01T11:00:00Z"), vec![thread("3", "2026-01-01T11:30:00Z", None)]).await;
+        let (selected, _) = poll(&fixture, since, &mut queued).await;
+        assert_eq!(ids(&selected), ["3"]);
+    }
+
+    #[tokio::test]
+    async fn since_last_interaction_skips_threads_read_or_acted_on_after_their_update() {
+        let fixture = fixture("poll_mode = \"since-last-interaction\"").await;
+        let account = fixture.client.account().name.clone();
+        let mut queued = HashMap::new();
+        let acted_at = DateTime::parse_from_rfc3339("2026-01-01T10:30:00Z").unwrap().with_timezone(&Utc);
+        fixture.manager.cache.mark_interacted(&account, "3", acted_at).unwrap();
+
+        serve(&fixture.server, None, vec![
+            thread("1", "2026-01-01T10:00:00Z", None),
+            thread("2", "2026-01-01T09:00:00Z", Some("2026-01-01T09:30:00Z")),
+            thread("3", "2026-01-01T10:00:00Z", None),
+        ]).await;
+        let (selected, processed_until) = poll(&fixture, None, &mut queued).await;
+        assert_eq!(ids(&selected), ["1"]);
+
+        let since = NotificationManager::next_since(PollMode::SinceLastInteraction, None, at("2026-01-01T11:00:00Z"), processed_until, false);
+        assert_eq!(since, Some(at("2026-01-01T10:00:00Z")));
+
+        // New activity after the popup action brings thread 3 back
+        serve(&fixture.server, Some("2026-01-01T10:00:00Z"), vec![thread("3", "2026-01-01T11:00:00Z", None)]).await;
+        let (selected, _) = poll(&fixture, since, &mut queued).await;
+        assert_eq!(ids(&selected), ["3"]);
+    }
+
+    #[tokio::test]
+    async fn a_poll_stopped_at_max_pages_keeps_since() {
+        let fixture = fixture("poll_mode = \"since-last-check\"\nmax_pages = 1").await;
+        let last_check = at("2026-01-01T08:00:00Z");
+
+        Mock::given(method("GET"))
+            .and(path("/notifications"))
+            .and(query_param_is_missing("page"))
+            .respond_with(ResponseTemplate::new(200)
+                .insert_header("Link", format!("<{}/notifications?page=2>; rel=\"next\"", fixture.server.uri()).as_str())
+                .set_body_json(Value::Array(vec![thread("1", "2026-01-01T10:00:00Z", None)])))
+            .expect(1)
+            .mount(&fixture.server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/notifications"))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(Value::Array(vec![thread("2", "2026-01-01T09:00:00Z", None)])))
+            .expect(0)
+            .mount(&fixture.server)
+            .await;
+
+        let poll = fixture.client.fetch_notifications(Some(last_check), None).await.unwrap();
+        assert!(poll.truncated);
+        assert_eq!(ids(poll.notifications.as_deref().unwrap()), ["1"]);
+
+        // Thread 2 on the unfetched page is older than thread 1, moving `since` would skip it
+        let processed_until = poll.notifications.unwrap().iter().map(|n| n.updated_at).max();
+        let since = NotificationManager::next_since(PollMode::SinceLastCheck, Some(last_check), at("2026-01-01T11:00:00Z"), processed_until, poll.truncated);
+        assert_eq!(since, Some(last_check));
+    }
+
+    #[tokio::test]
+    async fn a_restart_after_the_retention_period_does_not_replay_unread_threads() {
+        let fixture = fixture("poll_mode = \"all-unread\"").await;
+        let account = fixture.client.account().name.clone();
+        let threads = vec![
+            thread("1", "2026-01-01T10:00:00Z", None),
+            thread("2", "2026-01-01T09:00:00Z", None),
+        ];
+
+        serve(&fixture.server, None, threads.clone()).await;
+        let (selected, _) = poll(&fixture, None, &mut HashMap::new()).await;
+        assert_eq!(ids(&selected), ["1", "2"]);
+        for n in &selected {
+            fixture.manager.cache.mark_shown(&account, &n.id, n.updated_at).unwrap();
+        }
+
+        // Later polls only ask for newer activity, so the rows are not fetched again
+        let conn = rusqlite::Connection::open(fixture._dir.path().join("config.db")).unwrap();
+        conn.execute(
+            "UPDATE notifications SET fetched_at = fetched_at - ?",
+            [(CACHE_RETENTION + std::time::Duration::from_secs(24 * 60 * 60)).as_secs() as i64],
+        ).unwrap();
+
+        // The restart prunes nothing and lists every unread thread again, thread 2 was read meanwhile
+        assert_eq!(fixture.manager.cache.prune(PollMode::AllUnread, CACHE_RETENTION).unwrap(), 0);
+        let listed_at = Utc::now();
+        serve(&fixture.server, None, threads[..1].to_vec()).await;
+        let (selected, _) = poll(&fixture, None, &mut HashMap::new()).await;
+        assert!(selected.is_empty());
+
+        assert_eq!(fixture.manager.cache.prune_unlisted(&account, listed_at).unwrap(), 1);
+        let updated_at = DateTime::parse_from_rfc3339("2026-01-01T10:00:00Z").unwrap().with_timezone(&Utc);
+        assert!(!fixture.manager.cache.is_new_or_updated(&account, "1", updated_at).unwrap());
+    }
+}
@@ -1,11 +1,37 @@
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 use chrono::Utc;
 use glib::{DateTime, TimeZone};
-use notify_rust::{Hint, Notification, Timeout};
-use crate::github::github::{fetch_issue_comment, fetch_notification_details, mark_notification_as_read, CommentDto, NotificationDetailDto, NotificationDetailLinkHref, NotificationDetailLinks, NotificationDto};
+use tokio_util::sync::CancellationToken;
+use notify_rust::{CloseReason, Hint, Notification, Urgency};
+use crate::app_config::{AccountConfig, AppConfig, ClosePolicy, PopupAction};
+use crate::github::error::GithubError;
+use crate::github::github::{CommentDto, GithubClient, NotificationDetailDto, NotificationDto, UserDto};
+use crate::notify::avatar_cache::AvatarCache;
+use crate::notify::classifier::classify;
+use crate::notify::delivery_queue::{Delivery, DeliveryQueue};
+use crate::notify::inline_reply::{self, REPLY_ACTION};
+use crate::notify::notification_cache::NotificationCache;
+use crate::notify::popup_events::{self, PopupResponse};
+use crate::notify::popup_body::{body_markup_supported, escape_markup, render_body};
+use crate::notify::quick_actions::{self, QuickAction};
 use crate::notify::snooze_config_store::SnoozeConfigStore;
 
-#[derive(Debug)]
+/// What popups share with the notification manager: the config and the databases it already opened
+#[derive(Debug, Clone)]
+pub struct PopupContext {
+    pub config: Arc<AppConfig>,
+    pub store: Arc<SnoozeConfigStore>,
+    pub cache: Arc<NotificationCache>,
+    /// Popups shown again later go back through the queue, so quiet hours and pacing apply to them
+    pub queue: DeliveryQueue,
+    /// Ends the wait for popup actions when the app quits
+    pub cancellation_token: Arc<CancellationToken>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum NotificationType {
     Mentions,
     PullRequestOpened,
@@ -20,96 +46,633 @@ pub enum NotificationType {
     IssueCommented,
     PullRequestReviewRequested,
     PullRequestReviewRequestRemoved,
+    /// Releases, CI runs, discussions and anything the classifier cannot tell apart
+    Other,
 }
 
-fn open_browser(notification: &NotificationDto, details: &Option<NotificationDetailDto>, comment: &Option<CommentDto>) {
+impl NotificationType {
+    /// Types offered in the tray "Notification types" submenu
+    pub const ALL: [NotificationType; 10] = [
+        NotificationType::Mentions,
+        NotificationType::PullRequestReviewRequested,
+        NotificationType::PullRequestOpened,
+        NotificationType::PullRequestMerged,
+        NotificationType::PullRequestClosed,
+        NotificationType::IssueAssigned,
+        NotificationType::IssueCommented,
+        NotificationType::IssueOpened,
+        NotificationType::IssueClosed,
+        NotificationType::Other,
+    ];
+
+    /// Stable name used in menu ids and in the DB
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            NotificationType::Mentions => "mentions",
+            NotificationType::PullRequestOpened => "pull-request-opened",
+            NotificationType::PullRequestClosed => "pull-request-closed",
+            NotificationType::PullRequestMerged => "pull-request-merged",
+            NotificationType::IssueOpened => "issue-opened",
+            NotificationType::IssueClosed => "issue-closed",
+            NotificationType::IssueAssigned => "issue-assigned",
+            NotificationType::IssueUnassigned => "issue-unassigned",
+            NotificationType::IssueLabeled => "issue-labeled",
+            NotificationType::IssueUnlabeled => "issue-unlabeled",
+            NotificationType::IssueCommented => "issue-commented",
+            NotificationType::PullRequestReviewRequested => "pull-request-review-requested",
+            NotificationType::PullRequestReviewRequestRemoved => "pull-request-review-request-removed",
+            NotificationType::Other => "other",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<NotificationType> {
+        NotificationType::ALL.into_iter().find(|t| t.as_str() == name)
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            NotificationType::Mentions => "Mentions",
+            NotificationType::PullRequestOpened => "Pull request opened",
+            NotificationType::PullRequestClosed => "Pull request closed",
+            NotificationType::PullRequestMerged => "Pull request merged",
+            NotificationType::IssueOpened => "Issue opened",
+            NotificationType::IssueClosed => "Issue closed",
+            NotificationType::IssueAssigned => "Assigned",
+            NotificationType::IssueUnassigned => "Unassigned",
+            NotificationType::IssueLabeled => "Labeled",
+            NotificationType::IssueUnlabeled => "Unlabeled",
+            NotificationType::IssueCommented => "Comments",
+            NotificationType::PullRequestReviewRequested => "Review requested",
+            NotificationType::PullRequestReviewRequestRemoved => "Review request removed",
+            NotificationType::Other => "Other",
+        }
+    }
+
+    /// Icon inside `assets_dir`
+    pub fn icon(&self) -> &'static str {
+        match self {
+            NotificationType::PullRequestOpened | NotificationType::PullRequestReviewRequested => "pr-open.png",
+            NotificationType::PullRequestMerged => "pr-merged.png",
+            NotificationType::PullRequestClosed => "pr-closed.png",
+            _ => "github.png",
+        }
+    }
+}
 
-    if details.is_none() {
-        eprintln!("Notificattion {} is missing details", notification.id);
+/// Error popup for a failed GitHub call, missing resources and rate limits are only logged
+pub fn notify_github_error(config: &AppConfig, title: &str, error: &GithubError) {
+    if matches!(error, GithubError::NotFound | GithubError::RateLimited { .. }) {
         return;
     }
 
-    mark_notification_as_read(&notification.id);
+    let mut popup = Notification::new();
+    popup.summary(title).body(&error.to_string());
+    if let Err(e) = popup.image(config.asset("github-error.png")) {
+        eprintln!("Failed to load popup image: {}", e);
+    }
+    if let Err(e) = popup.show() {
+        eprintln!("Failed to show error popup: {}", e);
+    }
+}
+
+pub async fn mark_as_read(client: &GithubClient, notification: &NotificationDto) {
+    if let Err(e) = client.mark_notification_as_read(&notification.id).await {
+        eprintln!("[{}] Failed to mark notification {} as read: {}", client.account().name, notification.id, e);
+    }
+}
+
+/// Icon of a popup, bundled in `assets_dir`, taken from the desktop icon theme or an avatar
+enum PopupIcon {
+    Asset(&'static str),
+    Theme(&'static str),
+    Image(PathBuf),
+}
+
+/// Whose avatar stands for the thread: the latest commenter, else the author of the subject
+fn avatar_user<'a>(details: Option<&'a NotificationDetailDto>, comment: Option<&'a CommentDto>) -> Option<&'a UserDto> {
+    comment.and_then(|comment| comment.user.as_ref()).or(match details? {
+        NotificationDetailDto::PullRequest(pr) => pr.user.as_ref(),
+        NotificationDetailDto::Issue(issue) => issue.user.as_ref(),
+        _ => None,
+    })
+}
+
+/// Asset showing the subject state: an open, draft, merged or closed pull request, an open or closed issue
+fn state_icon(details: Option<&NotificationDetailDto>) -> Option<&'static str> {
+    match details? {
+        NotificationDetailDto::PullRequest(pr) => Some(match (pr.state.as_str(), pr.merged, pr.draft) {
+            ("closed", true, _) => "pr-merged.png",
+            ("closed", false, _) => "pr-closed.png",
+            (_, _, true) => "pr-draft.png",
+            _ => "pr-open.png",
+        }),
+        NotificationDetailDto::Issue(issue) => Some(match issue.state.as_str() {
+            "closed" => "issue-closed.png",
+            _ => "issue-open.png",
+        }),
+        _ => None,
+    }
+}
+
+async fn avatar_icon(
+    config: &AppConfig,
+    details: Option<&NotificationDetailDto>,
+    comment: Option<&CommentDto>,
+) -> Option<PopupIcon> {
+    if !config.avatars.enabled {
+        return None;
+    }
+    let user = avatar_user(details, comment)?;
+    let avatar_url = user.avatar_url.as_deref()?;
+    let badge = state_icon(details).map(|icon| config.asset(icon));
+
+    let cache = AvatarCache::open_default(config)
+        .map_err(|e| eprintln!("Failed to open the avatar cache: {}", e))
+        .ok()?;
+    cache.popup_image(&user.login, avatar_url, badge.as_deref()).await.map(PopupIcon::Image)
+}
+
+/// Icon of the type when it has its own, else the subject state, else one for the subject type
+fn popup_icon(notification_type: NotificationType, details: Option<&NotificationDetailDto>, subject_type: &str) -> PopupIcon {
+    if notification_type.icon() != "github.png" {
+        return PopupIcon::Asset(notification_type.icon());
+    }
+    if let Some(icon) = state_icon(details) {
+        return PopupIcon::Asset(icon);
+    }
+    match subject_type {
+        "Release" => PopupIcon::Theme("software-update-available"),
+        "Discussion" => PopupIcon::Theme("internet-group-chat"),
+        "CheckSuite" => PopupIcon::Theme("system-run"),
+        "Commit" => PopupIcon::Theme("document-save"),
+        "RepositoryVulnerabilityAlert" => PopupIcon::Theme("dialog-warning"),
+        _ => PopupIcon::Asset("github.png"),
+    }
+}
+
+/// Page to open when the subject has no details, e.g. discussions and check suites have no API URL
+fn fallback_url(account: &AccountConfig, notification: &NotificationDto) -> String {
+    let repo = format!("{}/{}", account.web_url(), notification.repository.full_name);
+    match notification.subject.type_field.as_str() {
+        "Discussion" => format!("{}/discussions", repo),
+        "CheckSuite" => format!("{}/actions", repo),
+        "Release" => format!("{}/releases", repo),
+        "RepositoryVulnerabilityAlert" => format!("{}/security/dependabot", repo),
+        _ => repo,
+    }
+}
+
+async fn open_browser(client: &GithubClient, notification: &NotificationDto, details: &Option<NotificationDetailDto>, comment: &Option<CommentDto>) {
+    mark_as_read(client, notification).await;
 
-    let url: &str = if let Some(comment) = comment {
-        comment.url.as_str()
-    } else if let Some(details) = details {
-        details.links.html.href.as_str()
+    let url = if let Some(comment) = comment {
+        comment.url.clone()
+    } else if let Some(url) = details.as_ref().and_then(|details| details.html_url()) {
+        url.to_string()
     } else {
-        println!("No URL found to open.");
-        return;
+        fallback_url(client.account(), notification)
     };
 
     eprintln!("Opening browser for notification  {} at {} ", notification.id, url);
-    if let Err(e) = webbrowser::open(url) {
+    if let Err(e) = webbrowser::open(&url) {
         eprintln!("Failed to open browser: {}", e);
     }
 }
-fn only_mark_as_read(notification: &NotificationDto) {
-    mark_notification_as_read(&notification.id);
+async fn only_mark_as_read(client: &GithubClient, notification: &NotificationDto) {
+    mark_as_read(client, notification).await;
+}
+
+/// Records that the thread got its popup, or was handled without one, so later polls skip it.
+/// Only done once it happened: a popup still queued when the app quits is shown after a restart.
+fn mark_shown(context: &PopupContext, account: &AccountConfig, notification: &NotificationDto) {
+    if let Err(e) = context.cache.mark_shown(&account.name, &notification.id, notification.updated_at) {
+        eprintln!("[{}] Failed to record popup of notification {}: {}", account.name, notification.id, e);
+    }
+}
+
+/// Posts the text of the reply box as a comment on the issue or pull request and marks the thread read
+async fn reply(config: &AppConfig, client: &GithubClient, notification: &NotificationDto, text: &str) {
+    let (Some(number), false) = (notification.subject.number(), text.trim().is_empty()) else {
+        return;
+    };
+    match client.post_comment(&notification.repository.full_name, number, text).await {
+        Ok(()) => mark_as_read(client, notification).await,
+        Err(e) => {
+            eprintln!("[{}] Failed to reply to notification {}: {}", client.account().name, notification.id, e);
+            notify_github_error(config, "Failed to post reply", &e);
+        }
+    }
+}
+
+/// Delay of the "Snooze 1h" popup action and of popups re-queued on close
+const SNOOZE_THREAD_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// Queues the popup again after `delay`, the thread stays unread meanwhile
+fn redeliver_later(context: &PopupContext, client: GithubClient, notification: NotificationDto, delay: Duration) {
+    let queue = context.queue.clone();
+    let cancellation_token = context.cancellation_token.clone();
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => {}
+            _ = tokio::time::sleep(delay) => queue.push(client, Delivery::Single(Box::new(notification))),
+        }
+    });
 }
-pub async fn github_notification(notification: NotificationDto) {
-    let details = fetch_notification_details(notification.subject.url.as_str());
+
+/// Action id and label of a configured popup button
+fn popup_button(action: PopupAction) -> (&'static str, &'static str) {
+    match action {
+        PopupAction::MarkRead => ("clicked_a", "✅ Mark as read"),
+        PopupAction::Open => ("clicked_b", "🌐 Open in browser"),
+        PopupAction::Snooze1h => ("snooze_1h", "⏰ Snooze 1h"),
+        PopupAction::Mute => ("mute", "🔕 Mute thread"),
+        PopupAction::Unsubscribe => ("unsubscribe", "🚫 Unsubscribe"),
+        PopupAction::SnoozeRepo => ("snooze_repo", "💤 Snooze this repo"),
+        PopupAction::OpenRepo => ("open_repo", "📁 Open repository"),
+    }
+}
+
+/// Mutes the thread on GitHub, it stays in the inbox only until read
+async fn mute_thread(client: &GithubClient, notification: &NotificationDto) {
+    if let Err(e) = client.ignore_thread(&notification.id).await {
+        eprintln!("[{}] Failed to mute notification {}: {}", client.account().name, notification.id, e);
+        return;
+    }
+    mark_as_read(client, notification).await;
+}
+
+async fn unsubscribe_thread(client: &GithubClient, notification: &NotificationDto) {
+    if let Err(e) = client.unsubscribe_thread(&notification.id).await {
+        eprintln!("[{}] Failed to unsubscribe from notification {}: {}", client.account().name, notification.id, e);
+        return;
+    }
+    mark_as_read(client, notification).await;
+}
+
+/// Snoozes the whole repository for the shortest duration offered in the tray
+fn snooze_repo(context: &PopupContext, account: &AccountConfig, notification: &NotificationDto) {
+    let days = context.config.snooze.durations_days.iter().min().copied().unwrap_or(1);
+    let until = DateTime::now_utc().unwrap().add_days(days as i32).unwrap();
+    let result = context.store.snooze_repo(
+        &account.name,
+        &notification.repository.owner.login,
+        &notification.repository.full_name,
+        until,
+    );
+    if let Err(e) = result {
+        eprintln!("[{}] Failed to snooze {}: {}", account.name, notification.repository.full_name, e);
+    }
+}
+
+fn open_repo(account: &AccountConfig, notification: &NotificationDto) {
+    let url = format!("{}/{}", account.web_url(), notification.repository.full_name);
+    eprintln!("Opening browser for repository {} at {} ", notification.repository.full_name, url);
+    if let Err(e) = webbrowser::open(&url) {
+        eprintln!("Failed to open browser: {}", e);
+    }
+}
+
+pub async fn github_notification(context: PopupContext, client: GithubClient, notification: NotificationDto) {
+    let config = context.config.clone();
+    let account = client.account();
+    let details = match client.fetch_notification_details(&notification.subject).await {
+        Ok(details) => details,
+        Err(e) => {
+            // The notification is still shown, just without state and link
+            eprintln!("[{}] Failed to fetch details of notification {}: {}", account.name, notification.id, e);
+            notify_github_error(&config, "Failed to fetch notification details", &e);
+            None
+        }
+    };
     let latest_comment =
         match &notification.subject.latest_comment_url {
             Some(comment_url) => {
-                fetch_issue_comment(comment_url.as_str())
+                client.fetch_issue_comment(comment_url.as_str())
+                    .await
+                    .map_err(|e| eprintln!("[{}] Failed to fetch comment {}: {}", account.name, comment_url, e))
+                    .ok()
             }
             None => None
         };
 
-    let image = match notification.subject.type_field.as_str() {
-        "PullRequest" => {
-            if let Some(pr) = &details {
-                match (pr.state.as_str(), pr.merged ) {
-                    ("open", _) => "./assets/pr-open.png",
-                    ("closed", true) => "./assets/pr-merged.png",
-                    ("closed", false) => "./assets/pr-closed.png",
-                    _ => "./assets/github.png", // fallback
-                }
-            } else {
-                "./assets/github-pr.png" // fallback if details not fetched
-            }
-        },
-        _ => "./assets/github.png",
+    let notification_type = classify(&notification, details.as_ref());
+    let icon = match avatar_icon(&config, details.as_ref(), latest_comment.as_ref()).await {
+        Some(icon) => icon,
+        None => popup_icon(notification_type, details.as_ref(), &notification.subject.type_field),
     };
 
-    let mut store = Arc::new(SnoozeConfigStore::open_default().unwrap());
+    let store = &context.store;
     let should_snooze = store.should_snooze_for_reason(
+        &account.name,
         &notification.repository.owner.login,
         &notification.repository.full_name,
         &notification.reason,
         DateTime::now_utc().unwrap(),
     ).unwrap_or(false);
 
+    // Muted types are left unread on GitHub, only the popup is skipped
+    if !should_snooze && store.is_notification_type_muted(notification_type.as_str()).unwrap_or(false) {
+        eprintln!("[{}] Notification {} is a muted {}, skipping popup.", account.name, notification.id, notification_type.as_str());
+        mark_shown(&context, account, &notification);
+        return;
+    }
 
     if !should_snooze {
-        let handle = Notification::new()
-            .summary(&notification.repository.full_name)
-            .body(&notification.subject.title)
-            .id((notification.id.parse::<u64>().unwrap()  % u32::MAX as u64) as u32)
-            .timeout(Timeout::Never)
-            .action("default", "default")
-            .action("clicked_a", "✅ Mark as read")
-            .action("clicked_b", "🌐 Open in browser")
-
-            .image(image)
-            .unwrap()
-            .show()
-            .unwrap();
-
-
-
-        handle.wait_for_action(|action| match action {
-            "default" => open_browser(&notification, &details, &latest_comment),
-            "clicked_a" => only_mark_as_read(&notification),
-            "clicked_b" => open_browser(&notification, &details, &latest_comment),
-            "__closed" => only_mark_as_read(&notification),
-            _ => println!("Not matching Action: {} ", action),
-        });
+        let markup = tokio::task::spawn_blocking(body_markup_supported).await.unwrap_or(false);
+        let popup_id = (notification.id.parse::<u64>().unwrap()  % u32::MAX as u64) as u32;
+        let reply_offered = notification.clone();
+        let reply_offered = tokio::task::spawn_blocking(move || inline_reply::offered(&reply_offered)).await.unwrap_or(false);
+
+        let mut popup = Notification::new();
+        popup
+            .summary(&format!("[{}] {}", account.name, notification.repository.full_name))
+            .body(&render_body(&notification, details.as_ref(), latest_comment.as_ref(), markup))
+            .id(popup_id)
+            .timeout(config.delivery.timeout(&notification.reason))
+            .urgency(config.delivery.urgency(&notification.reason))
+            .action("default", "default");
+        // Servers showing only the first few buttons keep the ones offered for this thread
+        for action in quick_actions::available(&config, &notification, details.as_ref(), latest_comment.as_ref()) {
+            popup.action(action.id(), &action.label(&config));
+        }
+        if reply_offered {
+            popup
+                .action(REPLY_ACTION, "💬 Reply")
+                .hint(Hint::Custom("x-kde-reply-placeholder-text".to_string(), "Reply on GitHub".to_string()));
+        }
+        for action in &config.popup.actions {
+            let (id, label) = popup_button(*action);
+            popup.action(id, label);
+        }
+        match icon {
+            PopupIcon::Asset(file_name) => {
+                if let Err(e) = popup.image(config.asset(file_name)) {
+                    eprintln!("Failed to load popup image: {}", e);
+                }
+            }
+            PopupIcon::Theme(name) => {
+                popup.icon(name);
+            }
+            PopupIcon::Image(path) => {
+                if let Err(e) = popup.image(&path) {
+                    eprintln!("Failed to load popup image {}: {}", path.display(), e);
+                    let _ = popup.image(config.asset("github.png"));
+                }
+            }
+        }
+        let Some(shown) = popup_events::show(&popup).await else {
+            return;
+        };
+        mark_shown(&context, account, &notification);
+
+        let response = shown.wait(&context.cancellation_token).await;
+
+        let interacted = matches!(
+            response,
+            Some(PopupResponse::Action(_) | PopupResponse::Reply(_) | PopupResponse::Closed(CloseReason::Dismissed))
+        );
+        if interacted {
+            if let Err(e) = context.cache.mark_interacted(&account.name, &notification.id, Utc::now()) {
+                eprintln!("[{}] Failed to record action on notification {}: {}", account.name, notification.id, e);
+            }
+        }
+
+        let action = match response {
+            Some(PopupResponse::Action(action)) => action,
+            Some(PopupResponse::Closed(CloseReason::Dismissed)) => {
+                match config.delivery.on_close {
+                    ClosePolicy::LeaveUnread => {}
+                    ClosePolicy::MarkRead => only_mark_as_read(&client, &notification).await,
+                    ClosePolicy::Requeue => redeliver_later(&context, client.clone(), notification.clone(), SNOOZE_THREAD_DELAY),
+                }
+                return;
+            }
+            Some(PopupResponse::Reply(text)) => {
+                reply(&config, &client, &notification, &text).await;
+                return;
+            }
+            Some(PopupResponse::Closed(reason)) => {
+                eprintln!("[{}] Popup of notification {} closed ({:?}), leaving it unread.", account.name, notification.id, reason);
+                return;
+            }
+            None => return,
+        };
+
+        match action.as_str() {
+            "default" => open_browser(&client, &notification, &details, &latest_comment).await,
+            "clicked_a" => only_mark_as_read(&client, &notification).await,
+            "clicked_b" => open_browser(&client, &notification, &details, &latest_comment).await,
+            "snooze_1h" => redeliver_later(&context, client.clone(), notification.clone(), SNOOZE_THREAD_DELAY),
+            "mute" => mute_thread(&client, &notification).await,
+            "unsubscribe" => unsubscribe_thread(&client, &notification).await,
+            "snooze_repo" => snooze_repo(&context, account, &notification),
+            "open_repo" => open_repo(account, &notification),
+            // Servers that announce a reply box but report a plain click instead
+            REPLY_ACTION => open_browser(&client, &notification, &details, &latest_comment).await,
+            id => match QuickAction::from_id(id) {
+                Some(quick_action) => quick_actions::run(&context, &client, &notification, quick_action).await,
+                None => println!("Not matching Action: {} ", action),
+            },
+        }
     } else {
-        only_mark_as_read(&notification)
+        mark_shown(&context, account, &notification);
+        only_mark_as_read(&client, &notification).await
     }
 
 
-}
\ No newline at end of file
+}
+/// Reason deciding the urgency and timeout of a popup covering several threads
+fn most_urgent_reason<'a>(config: &AppConfig, notifications: &'a [NotificationDto]) -> &'a str {
+    notifications.iter()
+        .map(|notification| notification.reason.as_str())
+        .max_by_key(|reason| match config.delivery.urgency(reason) {
+            Urgency::Low => 0,
+            Urgency::Normal => 1,
+            Urgency::Critical => 2,
+        })
+        .unwrap_or_default()
+}
+
+/// Type of a thread for the muted types check, the subject details are only fetched when the
+/// reason and the latest comment do not tell
+async fn notification_type(client: &GithubClient, notification: &NotificationDto) -> NotificationType {
+    let notification_type = classify(notification, None);
+    if notification_type != NotificationType::Other
+        || !matches!(notification.subject.type_field.as_str(), "Issue" | "PullRequest")
+    {
+        return notification_type;
+    }
+    let details = client.fetch_notification_details(&notification.subject)
+        .await
+        .map_err(|e| eprintln!("[{}] Failed to fetch details of notification {}: {}", client.account().name, notification.id, e))
+        .ok()
+        .flatten();
+    classify(notification, details.as_ref())
+}
+
+/// Marks snoozed threads as read and leaves muted types unread, like a single popup would, and returns the others
+async fn drop_skipped(context: &PopupContext, client: &GithubClient, notifications: Vec<NotificationDto>) -> Vec<NotificationDto> {
+    let account = client.account();
+    let store = &context.store;
+    // Spares the detail requests while no type is muted
+    let any_muted = NotificationType::ALL.iter().any(|t| store.is_notification_type_muted(t.as_str()).unwrap_or(false));
+
+    let mut shown = Vec::new();
+    for notification in notifications {
+        let should_snooze = store.should_snooze_for_reason(
+            &account.name,
+            &notification.repository.owner.login,
+            &notification.repository.full_name,
+            &notification.reason,
+            DateTime::now_utc().unwrap(),
+        ).unwrap_or(false);
+
+        if should_snooze {
+            mark_shown(context, account, &notification);
+            only_mark_as_read(client, &notification).await;
+            continue;
+        }
+
+        if any_muted {
+            let notification_type = notification_type(client, &notification).await;
+            if store.is_notification_type_muted(notification_type.as_str()).unwrap_or(false) {
+                eprintln!("[{}] Notification {} is a muted {}, leaving it out of the popup.", account.name, notification.id, notification_type.as_str());
+                mark_shown(context, account, &notification);
+                continue;
+            }
+        }
+        shown.push(notification);
+    }
+    shown
+}
+
+/// One popup for several threads of the same poll, "5 new in owner/repo", that can be expanded
+pub async fn grouped_notification(context: PopupContext, client: GithubClient, notifications: Vec<NotificationDto>) {
+    let config = context.config.clone();
+    let mut shown = drop_skipped(&context, &client, notifications).await;
+    if shown.len() <= 1 {
+        if let Some(notification) = shown.pop() {
+            github_notification(context, client, notification).await;
+        }
+        return;
+    }
+
+    let account = client.account();
+    let repo = shown[0].repository.full_name.clone();
+    // Titles can contain `<` or `&`, which servers with body markup would misread
+    let markup = tokio::task::spawn_blocking(body_markup_supported).await.unwrap_or(false);
+    let body = shown.iter()
+        .map(|notification| {
+            let title = if markup { escape_markup(&notification.subject.title) } else { notification.subject.title.clone() };
+            format!("• {} ({})", title, notification.reason)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut popup = Notification::new();
+    popup
+        .summary(&format!("[{}] {} new in {}", account.name, shown.len(), repo))
+        .body(&body)
+        .timeout(config.delivery.timeout(most_urgent_reason(&config, &shown)))
+        .urgency(config.delivery.urgency(most_urgent_reason(&config, &shown)))
+        .action("default", "default")
+        .action("expand", "📂 Show each")
+        .action("mark_all", "✅ Mark all as read");
+    if let Err(e) = popup.image(config.asset("github.png")) {
+        eprintln!("Failed to load popup image: {}", e);
+    }
+    let Some(popup) = popup_events::show(&popup).await else {
+        return;
+    };
+    for notification in &shown {
+        mark_shown(&context, account, notification);
+    }
+
+    // Closing the popup leaves the threads alone
+    let Some(PopupResponse::Action(action)) = popup.wait(&context.cancellation_token).await else {
+        return;
+    };
+
+    match action.as_str() {
+        // Back through the queue, so pacing, priority and quiet hours apply to each popup
+        "default" | "expand" => {
+            for notification in shown {
+                context.queue.push(client.clone(), Delivery::Single(Box::new(notification)));
+            }
+        }
+        "mark_all" => {
+            for notification in &shown {
+                only_mark_as_read(&client, notification).await;
+            }
+        }
+        _ => println!("Not matching Action: {} ", action),
+    }
+}
+
+/// Single summary popup for the threads batched by the digest, grouped by repository and reason
+pub async fn digest_notification(context: PopupContext, client: GithubClient, notifications: Vec<NotificationDto>) {
+    let config = context.config.clone();
+    let account = client.account();
+    let shown = drop_skipped(&context, &client, notifications).await;
+    if shown.is_empty() {
+        return;
+    }
+
+    let mut groups: BTreeMap<&str, BTreeMap<&str, usize>> = BTreeMap::new();
+    for notification in &shown {
+        *groups.entry(notification.repository.full_name.as_str())
+            .or_default()
+            .entry(notification.reason.as_str())
+            .or_default() += 1;
+    }
+    let body = groups.iter()
+        .map(|(repo, reasons)| {
+            let reasons = reasons.iter()
+                .map(|(reason, count)| format!("{} {}", count, reason))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!("{}: {}", repo, reasons)
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let mut popup = Notification::new();
+    popup
+        .summary(&format!("[{}] {} new GitHub notifications", account.name, shown.len()))
+        .body(&body)
+        .timeout(config.delivery.timeout(most_urgent_reason(&config, &shown)))
+        .urgency(config.delivery.urgency(most_urgent_reason(&config, &shown)))
+        .action("default", "default")
+        .action("open_list", "📋 Open list")
+        .action("mark_all", "✅ Mark all as read");
+    if let Err(e) = popup.image(config.asset("github.png")) {
+        eprintln!("Failed to load popup image: {}", e);
+    }
+    let Some(popup) = popup_events::show(&popup).await else {
+        return;
+    };
+    for notification in &shown {
+        mark_shown(&context, account, notification);
+    }
+
+    // Closing the popup leaves the threads alone
+    let Some(PopupResponse::Action(action)) = popup.wait(&context.cancellation_token).await else {
+        return;
+    };
+
+    match action.as_str() {
+        "default" | "open_list" => {
+            let url = format!("{}/notifications", account.web_url());
+            eprintln!("Opening browser for the notification list at {}", url);
+            if let Err(e) = webbrowser::open(&url) {
+                eprintln!("Failed to open browser: {}", e);
+            }
+        }
+        "mark_all" => {
+            for notification in &shown {
+                only_mark_as_read(&client, notification).await;
+            }
+        }
+        _ => println!("Not matching Action: {} ", action),
+    }
+}
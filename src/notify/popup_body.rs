@@ -0,0 +1,179 @@
+use std::sync::OnceLock;
+use chrono::Utc;
+use crate::github::github::{CommentDto, NotificationDetailDto, NotificationDto};
+
+/// Longest comment excerpt shown in a popup, in characters
+const EXCERPT_LENGTH: usize = 100;
+
+/// Whether the notification server renders `<b>`, `<i>` and friends, asked once per run
+pub fn body_markup_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(|| {
+        notify_rust::get_capabilities()
+            .map(|capabilities| capabilities.iter().any(|c| c == "body-markup"))
+            .unwrap_or(false)
+    })
+}
+
+/// "updated 5 min ago" style age of the last activity on a thread
+pub fn updated_ago(updated_at: chrono::DateTime<Utc>) -> String {
+    let minutes = (Utc::now() - updated_at).num_minutes().max(0);
+    match minutes {
+        0 => "updated just now".to_string(),
+        1..=59 => format!("updated {} min ago", minutes),
+        60..=1439 => format!("updated {} h ago", minutes / 60),
+        _ => format!("updated {} days ago", minutes / 1440),
+    }
+}
+
+/// Extra line of the popup body describing the subject state, when the details say more than the title
+fn details_line(details: Option<&NotificationDetailDto>) -> Option<String> {
+    match details? {
+        NotificationDetailDto::Issue(issue) if issue.state == "closed" => {
+            Some(format!("closed as {}", issue.state_reason.as_deref().unwrap_or("completed").replace('_', " ")))
+        }
+        NotificationDetailDto::Release(release) => {
+            let tag = release.tag_name.as_deref()?;
+            Some(if release.prerelease { format!("{} (pre-release)", tag) } else { tag.to_string() })
+        }
+        _ => None,
+    }
+}
+
+/// "+120/−4, draft" for pull requests
+fn stats(details: Option<&NotificationDetailDto>) -> Option<String> {
+    let Some(NotificationDetailDto::PullRequest(pr)) = details else {
+        return None;
+    };
+
+    let mut parts = Vec::new();
+    if let (Some(additions), Some(deletions)) = (pr.additions, pr.deletions) {
+        parts.push(format!("+{}/−{}", additions, deletions));
+    }
+    if pr.draft {
+        parts.push(String::from("draft"));
+    }
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+fn labels(details: Option<&NotificationDetailDto>) -> Option<String> {
+    let labels = match details? {
+        NotificationDetailDto::PullRequest(pr) => &pr.labels,
+        NotificationDetailDto::Issue(issue) => &issue.labels,
+        _ => return None,
+    };
+    (!labels.is_empty()).then(|| labels.iter().map(|label| label.name.as_str()).collect::<Vec<_>>().join(", "))
+}
+
+fn author(details: Option<&NotificationDetailDto>) -> Option<&str> {
+    match details? {
+        NotificationDetailDto::PullRequest(pr) => pr.user.as_ref().map(|user| user.login.as_str()),
+        NotificationDetailDto::Issue(issue) => issue.user.as_ref().map(|user| user.login.as_str()),
+        _ => None,
+    }
+}
+
+/// Plain text of a markdown comment: code blocks, links, images, emphasis and quotes are flattened
+fn strip_markdown(markdown: &str) -> String {
+    let mut text = String::new();
+    let mut in_code_block = false;
+
+    for line in markdown.lines() {
+        let line = line.trim();
+        if line.starts_with("```") {
+            in_code_block = !in_code_block;
+            if in_code_block {
+                text.push_str(" [code] ");
+            }
+            continue;
+        }
+        if in_code_block || line.starts_with("<!--") {
+            continue;
+        }
+        let line = line.trim_start_matches(['#', '>', ' ']);
+        let line = line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")).unwrap_or(line);
+        text.push_str(&strip_inline(line));
+        text.push(' ');
+    }
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// `[text](url)` and `![alt](url)` keep their text, emphasis and code markers are dropped
+fn strip_inline(line: &str) -> String {
+    let mut out = String::new();
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '_' | '~' | '`' => {}
+            '!' if chars.peek() == Some(&'[') => {}
+            ']' if chars.peek() == Some(&'(') => {
+                for c in chars.by_ref() {
+                    if c == ')' {
+                        break;
+                    }
+                }
+            }
+            '[' => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// First `EXCERPT_LENGTH` characters, cut at a word boundary
+fn excerpt(text: &str) -> String {
+    if text.chars().count() <= EXCERPT_LENGTH {
+        return text.to_string();
+    }
+    let cut: String = text.chars().take(EXCERPT_LENGTH).collect();
+    let cut = cut.rsplit_once(' ').map_or(cut.as_str(), |(head, _)| head);
+    format!("{}…", cut.trim_end_matches(|c: char| c.is_ascii_punctuation()))
+}
+
+/// Escapes the characters the body markup would interpret
+pub fn escape_markup(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Popup body: title, who did what with a comment excerpt and PR stats, labels and age.
+/// `markup` emphasizes the title and the author, the text is escaped accordingly.
+pub fn render_body(
+    notification: &NotificationDto,
+    details: Option<&NotificationDetailDto>,
+    comment: Option<&CommentDto>,
+    markup: bool,
+) -> String {
+    let text = |s: &str| if markup { escape_markup(s) } else { s.to_string() };
+    let bold = |s: &str| if markup { format!("<b>{}</b>", escape_markup(s)) } else { s.to_string() };
+
+    let mut lines = vec![bold(&notification.subject.title)];
+
+    let comment_body = comment.and_then(|comment| comment.body.as_deref()).map(strip_markdown);
+    let comment_author = comment.and_then(|comment| comment.user.as_ref()).map(|user| user.login.as_str());
+    let mut activity = match (comment_author, comment_body.as_deref(), author(details)) {
+        (Some(login), Some(body), _) if !body.is_empty() => format!("{}: {}", bold(login), text(&excerpt(body))),
+        (_, _, Some(login)) => format!("by {}", bold(login)),
+        _ => String::new(),
+    };
+    if let Some(stats) = stats(details) {
+        if activity.is_empty() {
+            activity = format!("({})", text(&stats));
+        } else {
+            activity = format!("{} ({})", activity, text(&stats));
+        }
+    }
+    if !activity.is_empty() {
+        lines.push(activity);
+    }
+
+    if let Some(line) = details_line(details) {
+        lines.push(text(&line));
+    }
+    if let Some(labels) = labels(details) {
+        lines.push(text(&format!("labels: {}", labels)));
+    }
+    lines.push(text(&updated_ago(notification.updated_at)));
+
+    lines.join("\n")
+}
@@ -0,0 +1,97 @@
+use futures_util::StreamExt;
+use notify_rust::{CloseReason, Notification};
+use tokio_util::sync::CancellationToken;
+use zbus::message::Type;
+use zbus::{Connection, MatchRule, MessageStream};
+
+const NOTIFICATIONS_INTERFACE: &str = "org.freedesktop.Notifications";
+
+/// How a popup went away
+#[derive(Debug)]
+pub enum PopupResponse {
+    Action(String),
+    Closed(CloseReason),
+    /// Text typed in the reply box
+    Reply(String),
+}
+
+/// Shows `popup`, listening to its signals first so a quick click or reply is not missed.
+/// `None` when the notification server refused it. Without the signals the popup is still shown,
+/// its response is then never known.
+pub async fn show(popup: &Notification) -> Option<ShownPopup> {
+    let events = PopupEvents::subscribe().await
+        .map_err(|e| eprintln!("Failed to listen for popup actions: {}", e))
+        .ok();
+    match popup.show() {
+        Ok(handle) => Some(ShownPopup { id: handle.id(), events }),
+        Err(e) => {
+            eprintln!("Failed to show popup: {}", e);
+            None
+        }
+    }
+}
+
+/// Popup on screen, see `show`
+pub struct ShownPopup {
+    /// Id the server gave the popup, the requested one is only a hint to replace an older popup
+    id: u32,
+    events: Option<PopupEvents>,
+}
+
+impl ShownPopup {
+    /// Waits for the user without holding a thread, `None` when the app quits first
+    pub async fn wait(self, cancellation_token: &CancellationToken) -> Option<PopupResponse> {
+        self.events?.wait(self.id, cancellation_token).await
+    }
+}
+
+/// Signals of the notification server, including the `NotificationReplied` one notify-rust does not listen to
+struct PopupEvents {
+    messages: MessageStream,
+}
+
+impl PopupEvents {
+    async fn subscribe() -> zbus::Result<PopupEvents> {
+        let connection = Connection::session().await?;
+        let rule = MatchRule::builder()
+            .msg_type(Type::Signal)
+            .interface(NOTIFICATIONS_INTERFACE)?
+            .build();
+        let messages = MessageStream::for_match_rule(rule, &connection, None).await?;
+        Ok(PopupEvents { messages })
+    }
+
+    /// Waits until popup `id` is replied to, acted on or closed, `None` when the app quits first or the bus goes away
+    async fn wait(mut self, id: u32, cancellation_token: &CancellationToken) -> Option<PopupResponse> {
+        tokio::select! {
+            _ = cancellation_token.cancelled() => None,
+            response = self.next_response(id) => response,
+        }
+    }
+
+    async fn next_response(&mut self, id: u32) -> Option<PopupResponse> {
+        while let Some(message) = self.messages.next().await {
+            let Ok(message) = message else {
+                break;
+            };
+            let header = message.header();
+            let body = message.body();
+            let response = match header.member().map(|member| member.as_str()) {
+                Some("NotificationReplied") => body.deserialize::<(u32, String)>().ok()
+                    .filter(|(popup, _)| *popup == id)
+                    .map(|(_, text)| PopupResponse::Reply(text)),
+                Some("ActionInvoked") => body.deserialize::<(u32, String)>().ok()
+                    .filter(|(popup, _)| *popup == id)
+                    .map(|(_, action)| PopupResponse::Action(action)),
+                Some("NotificationClosed") => body.deserialize::<(u32, u32)>().ok()
+                    .filter(|(popup, _)| *popup == id)
+                    .map(|(_, reason)| PopupResponse::Closed(CloseReason::from(reason))),
+                _ => None,
+            };
+            if response.is_some() {
+                return response;
+            }
+        }
+        None
+    }
+}
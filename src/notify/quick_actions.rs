@@ -0,0 +1,158 @@
+use notify_rust::{Notification, Timeout};
+use crate::app_config::AppConfig;
+use crate::github::github::{CommentDto, GithubClient, NotificationDetailDto, NotificationDto};
+use crate::notify::notify::{mark_as_read, notify_github_error, PopupContext};
+use crate::notify::popup_events::{self, PopupResponse};
+use crate::notify::popup_body::{body_markup_supported, escape_markup};
+
+/// Popup action acting on the subject of a thread, run only once confirmed in a second popup
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuickAction {
+    Approve,
+    Merge,
+    React,
+}
+
+impl QuickAction {
+    /// Action id of the popup button
+    pub fn id(&self) -> &'static str {
+        match self {
+            QuickAction::Approve => "approve",
+            QuickAction::Merge => "merge",
+            QuickAction::React => "react",
+        }
+    }
+
+    pub fn from_id(id: &str) -> Option<QuickAction> {
+        [QuickAction::Approve, QuickAction::Merge, QuickAction::React].into_iter().find(|action| action.id() == id)
+    }
+
+    pub fn label(&self, config: &AppConfig) -> String {
+        match self {
+            QuickAction::Approve => String::from("👍 Approve"),
+            QuickAction::Merge => String::from("🔀 Merge"),
+            QuickAction::React => format!("{} React", reaction_emoji(&config.quick_actions.reaction)),
+        }
+    }
+
+    fn question(&self) -> &'static str {
+        match self {
+            QuickAction::Approve => "Approve this pull request?",
+            QuickAction::Merge => "Merge this pull request?",
+            QuickAction::React => "React to the latest comment?",
+        }
+    }
+
+    fn failure(&self) -> &'static str {
+        match self {
+            QuickAction::Approve => "Failed to approve pull request",
+            QuickAction::Merge => "Failed to merge pull request",
+            QuickAction::React => "Failed to add reaction",
+        }
+    }
+}
+
+fn reaction_emoji(reaction: &str) -> &'static str {
+    match reaction {
+        "+1" => "👍",
+        "-1" => "👎",
+        "laugh" => "😄",
+        "confused" => "😕",
+        "heart" => "❤️",
+        "hooray" => "🎉",
+        "rocket" => "🚀",
+        _ => "👀",
+    }
+}
+
+/// Quick actions configured for the reason of the thread that its subject allows:
+/// open non-draft pull requests can be approved, merged only when GitHub reports them `clean`,
+/// and a reaction needs a latest comment other than the subject.
+pub fn available(
+    config: &AppConfig,
+    notification: &NotificationDto,
+    details: Option<&NotificationDetailDto>,
+    comment: Option<&CommentDto>,
+) -> Vec<QuickAction> {
+    let enabled = |reasons: &[String]| reasons.contains(&notification.reason);
+    let open_pull_request = match details {
+        Some(NotificationDetailDto::PullRequest(pr)) if pr.state == "open" && !pr.merged && !pr.draft => Some(pr),
+        _ => None,
+    };
+
+    let mut actions = Vec::new();
+    if enabled(&config.quick_actions.approve) && open_pull_request.is_some() {
+        actions.push(QuickAction::Approve);
+    }
+    if enabled(&config.quick_actions.merge)
+        && open_pull_request.is_some_and(|pr| pr.mergeable_state.as_deref() == Some("clean"))
+    {
+        actions.push(QuickAction::Merge);
+    }
+    // A latest comment equal to the subject is the subject itself, e.g. a new pull request
+    let latest_comment_url = notification.subject.latest_comment_url.as_ref()
+        .filter(|comment_url| Some(*comment_url) != notification.subject.url.as_ref());
+    if enabled(&config.quick_actions.react) && comment.is_some() && latest_comment_url.is_some() {
+        actions.push(QuickAction::React);
+    }
+    actions
+}
+
+/// Asks for confirmation, runs `action` and marks the thread read once it succeeded
+pub async fn run(context: &PopupContext, client: &GithubClient, notification: &NotificationDto, action: QuickAction) {
+    let config = &context.config;
+    if !confirm(context, notification, action).await {
+        eprintln!("[{}] {} on notification {} cancelled.", client.account().name, action.id(), notification.id);
+        return;
+    }
+
+    let result = match action {
+        QuickAction::Approve | QuickAction::Merge => {
+            let Some(url) = notification.subject.url.as_deref() else {
+                return;
+            };
+            if action == QuickAction::Approve {
+                client.approve_pull_request(url).await
+            } else {
+                client.merge_pull_request(url).await
+            }
+        }
+        QuickAction::React => {
+            let Some(url) = notification.subject.latest_comment_url.as_deref() else {
+                return;
+            };
+            client.add_reaction(url, &config.quick_actions.reaction).await
+        }
+    };
+
+    match result {
+        Ok(()) => mark_as_read(client, notification).await,
+        Err(e) => {
+            eprintln!("[{}] {} on notification {} failed: {}", client.account().name, action.id(), notification.id, e);
+            notify_github_error(config, action.failure(), &e);
+        }
+    }
+}
+
+async fn confirm(context: &PopupContext, notification: &NotificationDto, action: QuickAction) -> bool {
+    let config = &context.config;
+    let markup = tokio::task::spawn_blocking(body_markup_supported).await.unwrap_or(false);
+    let title = if markup { escape_markup(&notification.subject.title) } else { notification.subject.title.clone() };
+
+    let mut popup = Notification::new();
+    popup
+        .summary(action.question())
+        .body(&format!("{}\n{}", notification.repository.full_name, title))
+        .timeout(Timeout::Never)
+        .action("confirm", &action.label(config))
+        .action("cancel", "✖ Cancel");
+    if let Err(e) = popup.image(config.asset("github.png")) {
+        eprintln!("Failed to load popup image: {}", e);
+    }
+    let Some(popup) = popup_events::show(&popup).await else {
+        return false;
+    };
+
+    let response = popup.wait(&context.cancellation_token).await;
+    matches!(response, Some(PopupResponse::Action(action)) if action == "confirm")
+}
@@ -2,7 +2,7 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use glib::DateTime;
 use rusqlite::{params, Connection, OptionalExtension};
-use crate::{CONFIG_DIR_NAME, DB_FILE_NAME};
+use crate::app_config::{AppConfig, DEFAULT_ACCOUNT_NAME};
 
 #[derive(Clone, Debug)]
 pub struct SnoozeConfigStore {
@@ -11,14 +11,12 @@ pub struct SnoozeConfigStore {
 
 impl SnoozeConfigStore {
 
-    /// Open (and create/migrate) the config DB under ~/.config/github-notifier/config.db
-    pub fn open_default() -> rusqlite::Result<Self> {
-        let dir = dirs::home_dir()
-            .expect("no home dir")
-            .join(CONFIG_DIR_NAME);
-        fs::create_dir_all(&dir).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
-        let path = dir.join(DB_FILE_NAME);
-        Self::open_at(path)
+    /// Open (and create/migrate) the configured DB, ~/.config/github-notifier/config.db by default
+    pub fn open_default(config: &AppConfig) -> rusqlite::Result<Self> {
+        if let Some(dir) = config.db_path.parent() {
+            fs::create_dir_all(dir).map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+        }
+        Self::open_at(&config.db_path)
     }
 
     /// Open at a specific path (useful for tests)
@@ -59,6 +57,62 @@ impl SnoozeConfigStore {
             );
             "#,
         )?;
+
+        let version: i64 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+        if version < 1 {
+            // v1: every row is tagged with the account it came from, rows created before
+            // multi-account support belong to the default account.
+            conn.execute_batch(&format!(
+                r#"
+                BEGIN;
+
+                ALTER TABLE snoozed_repositories RENAME TO snoozed_repositories_v0;
+                CREATE TABLE snoozed_repositories(
+                  account TEXT NOT NULL,
+                  owner   TEXT NOT NULL,
+                  repo    TEXT NOT NULL,
+                  until   INTEGER,
+                  reason  TEXT,
+                  created_at INTEGER NOT NULL DEFAULT (unixepoch('now')),
+                  updated_at INTEGER NOT NULL DEFAULT (unixepoch('now')),
+                  UNIQUE(account, owner, repo)
+                );
+                INSERT INTO snoozed_repositories(account, owner, repo, until, reason, created_at, updated_at)
+                  SELECT '{account}', owner, repo, until, reason, created_at, updated_at FROM snoozed_repositories_v0;
+                DROP TABLE snoozed_repositories_v0;
+
+                ALTER TABLE snoozed_repository_reasons RENAME TO snoozed_repository_reasons_v0;
+                CREATE TABLE snoozed_repository_reasons(
+                  account TEXT NOT NULL,
+                  owner TEXT NOT NULL,
+                  repo  TEXT NOT NULL,
+                  reason TEXT NOT NULL,
+                  UNIQUE(account, owner, repo, reason)
+                );
+                INSERT INTO snoozed_repository_reasons(account, owner, repo, reason)
+                  SELECT '{account}', owner, repo, reason FROM snoozed_repository_reasons_v0;
+                DROP TABLE snoozed_repository_reasons_v0;
+
+                PRAGMA user_version = 1;
+                COMMIT;
+                "#,
+                account = DEFAULT_ACCOUNT_NAME
+            ))?;
+        }
+
+        conn.execute_batch(
+            r#"
+            CREATE TABLE IF NOT EXISTS muted_notification_types(
+              type TEXT NOT NULL,
+              UNIQUE(type)
+            );
+
+            CREATE TABLE IF NOT EXISTS do_not_disturb(
+              id    INTEGER PRIMARY KEY CHECK (id = 0),
+              until INTEGER NOT NULL
+            );
+            "#,
+        )?;
         Ok(())
     }
 
@@ -73,76 +127,78 @@ impl SnoozeConfigStore {
     }
 
     /// Snooze a repo until a given UTC instant (overwrites existing)
-    pub fn snooze_repo(&self, owner: &str, repo: &str, until: DateTime) -> rusqlite::Result<()> {
+    pub fn snooze_repo(&self, account: &str, owner: &str, repo: &str, until: DateTime) -> rusqlite::Result<()> {
         let conn = self.connect()?;
         let until_sec = until.to_unix();
         conn.execute(
             r#"
-            INSERT INTO snoozed_repositories(owner, repo, until)
-            VALUES(?, ?, ?)
-            ON CONFLICT(owner, repo) DO UPDATE SET until=excluded.until
+            INSERT INTO snoozed_repositories(account, owner, repo, until)
+            VALUES(?, ?, ?, ?)
+            ON CONFLICT(account, owner, repo) DO UPDATE SET until=excluded.until
             "#,
-            params![owner, repo, until_sec],
+            params![account, owner, repo, until_sec],
         )?;
         Ok(())
     }
 
     /// Adds repository to table
-    pub fn add_repo(&self, owner: &str, repo: &str) -> rusqlite::Result<()> {
+    pub fn add_repo(&self, account: &str, owner: &str, repo: &str) -> rusqlite::Result<()> {
         let conn = self.connect()?;
 
         conn.execute(
             r#"
-            INSERT INTO snoozed_repositories(owner, repo)
-            VALUES(?, ?)
-            ON CONFLICT(owner, repo) DO NOTHING
+            INSERT INTO snoozed_repositories(account, owner, repo)
+            VALUES(?, ?, ?)
+            ON CONFLICT(account, owner, repo) DO NOTHING
             "#,
-            params![owner, repo],
+            params![account, owner, repo],
         )?;
         Ok(())
     }
 
     /// Unsnooze a repo
-    pub fn unsnooze_repo(&self, owner: &str, repo: &str) -> rusqlite::Result<bool> {
+    pub fn unsnooze_repo(&self, account: &str, owner: &str, repo: &str) -> rusqlite::Result<bool> {
         let conn = self.connect()?;
-        let changed = conn.execute("DELETE FROM snoozed_repositories WHERE owner=? AND repo=?", params![owner, repo])?;
+        let changed = conn.execute("DELETE FROM snoozed_repositories WHERE account=? AND owner=? AND repo=?", params![account, owner, repo])?;
         Ok(changed > 0)
     }
 
     /// Check repo snoozed
-    pub fn is_repo_snoozed(&self, owner: &str, repo: &str, now: DateTime) -> rusqlite::Result<bool> {
+    pub fn is_repo_snoozed(&self, account: &str, owner: &str, repo: &str, now: DateTime) -> rusqlite::Result<bool> {
         let conn = self.connect()?;
         let now_sec = now.to_unix();
         let until_opt: Option<i64> = conn.query_row(
-            "SELECT until FROM snoozed_repositories WHERE owner=? AND repo=?",
-            params![owner, repo],
+            "SELECT until FROM snoozed_repositories WHERE account=? AND owner=? AND repo=?",
+            params![account, owner, repo],
             |row| row.get(0),
         ).optional()?;
         Ok(matches!(until_opt, Some(until) if until > now_sec))
     }
 
 
-    pub fn list_snoozed_repos(&self, now: DateTime) -> rusqlite::Result<Vec<(String, String, DateTime)>> {
+    pub fn list_snoozed_repos(&self, now: DateTime) -> rusqlite::Result<Vec<(String, String, String, DateTime)>> {
         let conn = self.connect()?;
-        let mut stmt = conn.prepare("SELECT owner, repo, until FROM snoozed_repositories WHERE until > ? ORDER BY until DESC")?;
+        let mut stmt = conn.prepare("SELECT account, owner, repo, until FROM snoozed_repositories WHERE until > ? ORDER BY until DESC")?;
         let rows = stmt.query_map(params![now.seconds()], |row| {
-            let until: i64 = row.get(2)?;
+            let until: i64 = row.get(3)?;
             Ok((
                 row.get::<_, String>(0)?,
                 row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
                 DateTime::from_unix_utc(until).unwrap(),
             ))
         })?;
         Ok(rows.filter_map(Result::ok).collect())
     }
-    pub fn list_all_repos(&self) -> rusqlite::Result<Vec<(String, String)>> {
+    pub fn list_all_repos(&self) -> rusqlite::Result<Vec<(String, String, String)>> {
         let conn = self.connect()?;
-        let mut stmt = conn.prepare("SELECT owner, repo FROM snoozed_repositories ORDER BY repo ASC")?;
+        let mut stmt = conn.prepare("SELECT account, owner, repo FROM snoozed_repositories ORDER BY account ASC, repo ASC")?;
         let rows = stmt.query_map(params![], |row| {
 
             Ok((
                 row.get::<_, String>(0)?,
-                row.get::<_, String>(1)?
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?
             ))
         })?;
         Ok(rows.filter_map(Result::ok).collect())
@@ -150,6 +206,7 @@ impl SnoozeConfigStore {
 
     pub fn snooze_reason(
         &self,
+        account: &str,
         owner: &str,
         repo: &str,
         reason: Option<&str>,
@@ -157,8 +214,8 @@ impl SnoozeConfigStore {
         let conn = self.connect()?;
 
         let mut insert_stmt = conn.execute(
-            "INSERT OR IGNORE INTO snoozed_repository_reasons(owner, repo, reason) VALUES(?, ?, ?)",
-            params![owner, repo, reason.unwrap_or("")],
+            "INSERT OR IGNORE INTO snoozed_repository_reasons(account, owner, repo, reason) VALUES(?, ?, ?, ?)",
+            params![account, owner, repo, reason.unwrap_or("")],
         )?;
 
 
@@ -166,6 +223,7 @@ impl SnoozeConfigStore {
     }
     pub fn unsooze_reason(
         &self,
+        account: &str,
         owner: &str,
         repo: &str,
         reason: Option<&str>,
@@ -173,8 +231,8 @@ impl SnoozeConfigStore {
         let conn = self.connect()?;
 
         let mut insert_stmt = conn.execute(
-            "DELETE FROM snoozed_repository_reasons WHERE owner=? AND repo=? AND reason=?",
-            params![owner, repo, reason.unwrap_or("")],
+            "DELETE FROM snoozed_repository_reasons WHERE account=? AND owner=? AND repo=? AND reason=?",
+            params![account, owner, repo, reason.unwrap_or("")],
         )?;
 
 
@@ -184,6 +242,7 @@ impl SnoozeConfigStore {
     /// Toggle a single reason. Returns the NEW state (true = now snoozed).
     pub fn toggle_reason(
         &self,
+        account: &str,
         owner: &str,
         repo: &str,
         reason: &str,
@@ -193,23 +252,23 @@ impl SnoozeConfigStore {
 
         let exists: Option<i64> = tx.query_row(
             "SELECT 1 FROM snoozed_repository_reasons
-             WHERE owner=? AND repo=? AND reason=? LIMIT 1",
-            params![owner, repo, reason],
+             WHERE account=? AND owner=? AND repo=? AND reason=? LIMIT 1",
+            params![account, owner, repo, reason],
             |row| row.get(0),
         ).optional()?;
 
         let now_enabled = if exists.is_some() {
             tx.execute(
                 "DELETE FROM snoozed_repository_reasons
-                 WHERE owner=? AND repo=? AND reason=?",
-                params![owner, repo, reason],
+                 WHERE account=? AND owner=? AND repo=? AND reason=?",
+                params![account, owner, repo, reason],
             )?;
             false
         } else {
             tx.execute(
-                "INSERT OR IGNORE INTO snoozed_repository_reasons(owner, repo, reason)
-                 VALUES(?, ?, ?)",
-                params![owner, repo, reason],
+                "INSERT OR IGNORE INTO snoozed_repository_reasons(account, owner, repo, reason)
+                 VALUES(?, ?, ?, ?)",
+                params![account, owner, repo, reason],
             )?;
             true
         };
@@ -220,6 +279,7 @@ impl SnoozeConfigStore {
 
     pub fn is_repo_snoozed_for_reason(
         &self,
+        account: &str,
         owner: &str,
         repo: &str,
         reason: &str,
@@ -232,10 +292,10 @@ impl SnoozeConfigStore {
         r#"
             SELECT 1
               FROM snoozed_repository_reasons
-             WHERE owner = ?1 AND repo = ?2 AND reason = ?3
+             WHERE account = ?1 AND owner = ?2 AND repo = ?3 AND reason = ?4
              LIMIT 1
             "#,
-                params![owner, repo, reason],
+                params![account, owner, repo, reason],
                 |row| row.get(0),
             )
             .optional()?;
@@ -244,6 +304,7 @@ impl SnoozeConfigStore {
     }
     pub fn should_snooze_for_reason(
         &self,
+        account: &str,
         owner: &str,
         repo: &str,
         reason: &str,
@@ -259,19 +320,64 @@ impl SnoozeConfigStore {
               FROM (
                     SELECT 1
                       FROM snoozed_repositories
-                     WHERE owner = ?1 AND repo = ?2 AND until > ?3
+                     WHERE account = ?1 AND owner = ?2 AND repo = ?3 AND until > ?4
                     UNION ALL
                     SELECT 1
                       FROM snoozed_repository_reasons
-                     WHERE owner = ?1 AND repo = ?2 AND reason = ?4
+                     WHERE account = ?1 AND owner = ?2 AND repo = ?3 AND reason = ?5
                    )
              LIMIT 1
             "#,
-                params![owner, repo, now_sec, reason],
+                params![account, owner, repo, now_sec, reason],
                 |row| row.get(0),
             )
             .optional()?;
 
         Ok(any.is_some())
     }
-}
\ No newline at end of file
+
+    /// Toggle popups of a notification type, for every account. Returns the NEW state (true = now muted).
+    pub fn toggle_notification_type(&self, notification_type: &str) -> rusqlite::Result<bool> {
+        let conn = self.connect()?;
+        let removed = conn.execute("DELETE FROM muted_notification_types WHERE type=?", params![notification_type])?;
+        if removed > 0 {
+            return Ok(false);
+        }
+        conn.execute("INSERT OR IGNORE INTO muted_notification_types(type) VALUES(?)", params![notification_type])?;
+        Ok(true)
+    }
+
+    pub fn is_notification_type_muted(&self, notification_type: &str) -> rusqlite::Result<bool> {
+        let conn = self.connect()?;
+        let muted: Option<i64> = conn.query_row(
+            "SELECT 1 FROM muted_notification_types WHERE type=? LIMIT 1",
+            params![notification_type],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(muted.is_some())
+    }
+
+    /// Holds popups back until `until`, `None` turns do not disturb off
+    pub fn set_do_not_disturb(&self, until: Option<DateTime>) -> rusqlite::Result<()> {
+        let conn = self.connect()?;
+        match until {
+            Some(until) => conn.execute(
+                "INSERT INTO do_not_disturb(id, until) VALUES(0, ?) ON CONFLICT(id) DO UPDATE SET until=excluded.until",
+                params![until.to_unix()],
+            )?,
+            None => conn.execute("DELETE FROM do_not_disturb", [])?,
+        };
+        Ok(())
+    }
+
+    /// End of the do not disturb period, `None` when it is off or over at `now`
+    pub fn do_not_disturb_until(&self, now: DateTime) -> rusqlite::Result<Option<DateTime>> {
+        let conn = self.connect()?;
+        let until: Option<i64> = conn.query_row(
+            "SELECT until FROM do_not_disturb WHERE id=0 AND until > ?",
+            params![now.to_unix()],
+            |row| row.get(0),
+        ).optional()?;
+        Ok(until.and_then(|until| DateTime::from_unix_utc(until).ok()))
+    }
+}
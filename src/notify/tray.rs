@@ -2,6 +2,7 @@ use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
 use std::sync::{Arc, Mutex};
+use chrono::{Local, Utc};
 use glib::DateTime;
 use gtk::prelude::SocketExtManual;
 use log::info;
@@ -10,12 +11,14 @@ use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use tray_icon::{TrayIconBuilder, TrayIconEvent};
 use tokio_util::sync::CancellationToken;
 use crate::load_icon;
+use crate::app_config::AppConfig;
 
 use tray_icon::menu::{AboutMetadata, Menu, MenuEvent, MenuId, MenuItem, PredefinedMenuItem, Submenu, CheckMenuItem};
 use crate::notify::notify::NotificationType;
 use crate::notify::snooze_config_store::SnoozeConfigStore;
 
 pub struct Tray {
+    config: Arc<AppConfig>,
     cancellation_token: Arc<CancellationToken>,
     state: Arc<Mutex<TrayState>>,
     gui_recv: Arc<Mutex<UnboundedReceiver<GuiMessage>>>,
@@ -38,14 +41,33 @@ pub struct AuthorMenuItemData {
     pub count: String,
 }
 
+#[derive(Debug)]
+pub struct RateLimitMenuItemData {
+    pub account: String,
+    pub remaining: u32,
+    pub limit: Option<u32>,
+    pub reset: chrono::DateTime<Utc>,
+}
+
 #[derive(Debug)]
 pub enum GuiMessage {
     UpdateRepositories(RepositoryMenuItemData),
     UpdateAuthors(RepositoryMenuItemData),
+    UpdateRateLimit(RateLimitMenuItemData),
+    /// Do not disturb was turned on or off, the menu shows until when
+    DoNotDisturbChanged,
 
     Quit,
 }
 
+/// Choices of the tray "Do not disturb" submenu
+#[derive(Debug, Clone, Copy)]
+pub enum DoNotDisturb {
+    For(std::time::Duration),
+    UntilTomorrow,
+    Off,
+}
+
 #[derive(Debug)]
 pub enum SnoozeMessage {
     SnoozeAuthor(AuthorMenuItemData),
@@ -53,6 +75,7 @@ pub enum SnoozeMessage {
     SnoozeRepository(RepositoryMenuItemData),
     UnSnoozeRepository(RepositoryMenuItemData),
     ToggleNotificationType(NotificationType),
+    DoNotDisturb(DoNotDisturb),
     ShowMentions,
     ShowSetAsReviewer,
     Quit,
@@ -63,6 +86,7 @@ pub struct TrayState {
 
     pub repository_items: HashMap<String, RepositoryMenuItemData>,
     pub author_items: HashMap<String, RepositoryMenuItemData>,
+    pub rate_limits: HashMap<String, RateLimitMenuItemData>,
 }
 
 impl TrayState {
@@ -70,29 +94,43 @@ impl TrayState {
         Self {
             repository_items: HashMap::new(),
             author_items: HashMap::new(),
+            rate_limits: HashMap::new(),
         }
     }
 
     fn clear(&mut self) {
         self.repository_items.clear();
         self.author_items.clear();
+        self.rate_limits.clear();
+    }
+}
+// helper: menu label for a snooze duration
+fn snooze_label(days: u32) -> String {
+    match days {
+        1 => String::from("For a Day"),
+        7 => String::from("For a Week"),
+        30 => String::from("For a Month"),
+        days => format!("For {} Days", days),
     }
 }
+
 // helper: build a stable id for each repo action
-fn repo_action_id(owner: &str, repo: &str, action: &str) -> MenuId {
-    // id format: "repo/<owner>/<repo>/<action>"
-    MenuId::new(format!("repo:{}:{}:{}", owner, repo, action))
+fn repo_action_id(account: &str, owner: &str, repo: &str, action: &str) -> MenuId {
+    // id format: "repo:<account>:<owner>:<repo>:<action>"
+    MenuId::new(format!("repo:{}:{}:{}:{}", account, owner, repo, action))
 }
 
 impl Tray {
 
     pub fn new(
+        config: Arc<AppConfig>,
         cancellation_token: Arc<CancellationToken>,
         gui_recv: Arc<Mutex<UnboundedReceiver<GuiMessage>>>,
         snooze_send: Arc<Mutex<UnboundedSender<SnoozeMessage>>>,
         store: Arc<SnoozeConfigStore>
     ) -> Arc<Self> {
         Arc::new(Self {
+            config,
             cancellation_token,
             gui_recv,
             snooze_send,
@@ -101,9 +139,17 @@ impl Tray {
 
         })
     }
-    fn process_messages(self: &Arc<Self>) {
+    /// Returns true when the menu needs to be rebuilt
+    fn process_messages(self: &Arc<Self>) -> bool {
+        let mut changed = false;
         loop {
             match self.gui_recv.lock().unwrap().try_recv() {
+                Ok(GuiMessage::UpdateRateLimit(rate_limit)) => {
+                    let mut state = self.state.lock().unwrap();
+                    state.rate_limits.insert(rate_limit.account.clone(), rate_limit);
+                    changed = true;
+                }
+                Ok(GuiMessage::DoNotDisturbChanged) => changed = true,
                 Ok(msg) => {
                     // Process the message
                     info!("Received Gui message: {:?}", msg);
@@ -111,12 +157,12 @@ impl Tray {
                 Err(tokio::sync::mpsc::error::TryRecvError::Empty) => {
                     // No messages – yield to let other tasks run
                     info!("No Gui messages:");
-                    return;
+                    return changed;
                 }
                 Err(tokio::sync::mpsc::error::TryRecvError::Disconnected) => {
                     // Sender is dropped – exit the loop
                     info!("Gui channel disconnected:");
-                    break;
+                    return changed;
                 }
             }
         }
@@ -142,9 +188,14 @@ impl Tray {
     fn build_repositories_menu(self: &Arc<Tray>) -> Submenu {
         let repositories = self.store.list_all_repos().unwrap();
         let sub_menu = Submenu::new("Repositories", true);
-        let mut owners: HashMap<String, Submenu> = HashMap::new();
+        let mut accounts: Vec<(String, Submenu, HashMap<String, Submenu>)> = Vec::new();
 
-        for (owner, repo_name) in repositories {
+        for (account, owner, repo_name) in repositories {
+            // Rows come ordered by account, so a new account always starts a new group
+            if accounts.last().map(|(name, _, _)| name != &account).unwrap_or(true) {
+                accounts.push((account.clone(), Submenu::new(account.clone(), true), HashMap::new()));
+            }
+            let (_, _, owners) = accounts.last_mut().unwrap();
             let owner_menu = owners
                 .entry(owner.clone())
                 .or_insert_with(|| Submenu::new(owner.clone(), true));
@@ -152,15 +203,10 @@ impl Tray {
             let repo_menu = Submenu::new(repo_name.clone(), true);
             let snooze_menu = Submenu::new("Snooze", true);
             let filter_menu = Submenu::new("Snoose reasons", true);
-            let all_reasons = vec![
-                "assign", "author", "ci_activity", "comment", "manual", "mention",
-                "push", "review_requested", "security_alert", "state_change",
-                "subscribed", "team_mention", "your_activity",
-            ];
-
-            for reason in all_reasons {
-                let reason_id = repo_action_id(&owner, &repo_name, format!("reason:{}", reason).as_str());
-                let is_snoozed = self.store.is_repo_snoozed_for_reason(owner.as_str(), repo_name.as_str(), reason);
+            for reason in &self.config.tray.reasons {
+                let reason = reason.as_str();
+                let reason_id = repo_action_id(&account, &owner, &repo_name, format!("reason:{}", reason).as_str());
+                let is_snoozed = self.store.is_repo_snoozed_for_reason(account.as_str(), owner.as_str(), repo_name.as_str(), reason);
                 let reason_menu_item = CheckMenuItem::with_id(
                     reason_id,
                     reason,
@@ -175,49 +221,101 @@ impl Tray {
             repo_menu.append(&filter_menu);
 
             // give EACH item a unique id that encodes which repo/action it is
-            let snooze_tommorow_id = repo_action_id(&owner, &repo_name, "snooze:day");
-            let snooze_week_id = repo_action_id(&owner, &repo_name, "snooze:week");
-            let snooze_month_id = repo_action_id(&owner, &repo_name, "snooze:month");
-
-            let snooze_tomorrow = MenuItem::with_id(
-                snooze_tommorow_id,
-                "For a Day",
-                true,
-                None
-            );
-            let snooze_week = MenuItem::with_id(
-                snooze_week_id,
-                "For a Week",
-                true,
-                None
-            );
-            let snooze_month = MenuItem::with_id(
-                snooze_month_id,
-                "For a Month",
-                true,
-                None
-            );
-
-            snooze_menu.append(&snooze_tomorrow);
-            snooze_menu.append(&snooze_week);
-            snooze_menu.append(&snooze_month);
+            for days in &self.config.snooze.durations_days {
+                let snooze_id = repo_action_id(&account, &owner, &repo_name, format!("snooze:{}", days).as_str());
+                let snooze_item = MenuItem::with_id(
+                    snooze_id,
+                    snooze_label(*days),
+                    true,
+                    None
+                );
+                snooze_menu.append(&snooze_item);
+            }
 
             owner_menu.append(&repo_menu);
         }
 
-        for (_, submenu) in owners {
-            sub_menu.append(&submenu);
+        for (_, account_menu, owners) in accounts {
+            for (_, submenu) in owners {
+                account_menu.append(&submenu);
+            }
+            sub_menu.append(&account_menu);
         }
         sub_menu
     }
+    fn build_notification_types_menu(self: &Arc<Tray>) -> Submenu {
+        let sub_menu = Submenu::new("Notification types", true);
+        for notification_type in NotificationType::ALL {
+            let muted = self.store.is_notification_type_muted(notification_type.as_str()).unwrap_or(false);
+            let item = CheckMenuItem::with_id(
+                MenuId::new(format!("type:{}", notification_type.as_str())),
+                notification_type.label(),
+                true,
+                !muted,
+                None
+            );
+            sub_menu.append(&item).unwrap();
+        }
+        sub_menu
+    }
+    fn build_do_not_disturb_menu(self: &Arc<Tray>) -> Submenu {
+        let until = self.store.do_not_disturb_until(DateTime::now_utc().unwrap()).unwrap_or(None);
+        let title = match until.as_ref().and_then(|until| until.to_local().ok()).and_then(|until| until.format("%H:%M").ok()) {
+            Some(until) => format!("Do not disturb until {}", until),
+            None => String::from("Do not disturb"),
+        };
+        let sub_menu = Submenu::new(title, true);
+        sub_menu.append_items(&[
+            &MenuItem::with_id("dnd:1h", "For 1 Hour", true, None),
+            &MenuItem::with_id("dnd:4h", "For 4 Hours", true, None),
+            &MenuItem::with_id("dnd:tomorrow", "Until Tomorrow", true, None),
+            &MenuItem::with_id("dnd:off", "Off", until.is_some(), None),
+        ]).unwrap();
+        sub_menu
+    }
+    fn build_rate_limit_items(self: &Arc<Tray>) -> Vec<MenuItem> {
+        let state = self.state.lock().unwrap();
+        let mut rate_limits: Vec<&RateLimitMenuItemData> = state.rate_limits.values().collect();
+        rate_limits.sort_by(|a, b| a.account.cmp(&b.account));
+
+        rate_limits.into_iter()
+            .map(|rate_limit| {
+                let quota = match rate_limit.limit {
+                    Some(limit) => format!("{}/{}", rate_limit.remaining, limit),
+                    None => rate_limit.remaining.to_string(),
+                };
+                let label = format!(
+                    "[{}] {} requests left, resets at {}",
+                    rate_limit.account,
+                    quota,
+                    rate_limit.reset.with_timezone(&Local).format("%H:%M")
+                );
+                // Informational only
+                MenuItem::new(label, false, None)
+            })
+            .collect()
+    }
+
     fn regenerate_menu(self: &Arc<Self>) -> Box<Menu> {
         let quit_id = "quit";
 
         let quit_menu_item = MenuItem::with_id(quit_id, "Quit", true, None);
         let menu = Box::new(Menu::new());
 
-        let submenu = self.build_repositories_menu();
-        menu.append(&submenu);
+        let rate_limit_items = self.build_rate_limit_items();
+        for item in &rate_limit_items {
+            menu.append(item).unwrap();
+        }
+        if !rate_limit_items.is_empty() {
+            menu.append(&PredefinedMenuItem::separator()).unwrap();
+        }
+
+        if self.config.tray.show_repositories {
+            let submenu = self.build_repositories_menu();
+            menu.append(&submenu);
+        }
+        menu.append(&self.build_notification_types_menu()).unwrap();
+        menu.append(&self.build_do_not_disturb_menu()).unwrap();
 
 
         menu.append_items(&[
@@ -237,7 +335,7 @@ impl Tray {
     }
     async fn setup_gtk_gui(self: Arc<Self>) {
 
-        let icon = load_icon(std::path::Path::new("./assets/github.png"));
+        let icon = load_icon(&self.config.asset("github.png"));
         // let (_, gui_rx) = MainContext::channel(gtk::glib::Priority::default());
         gtk::init().unwrap();
 
@@ -268,32 +366,40 @@ impl Tray {
             if id == "quit" {
                 moved_self.clone().cancellation_token.cancel();
             }
-            if let Some(rest) = id.strip_prefix("repo:") {
+            if let Some(notification_type) = id.strip_prefix("type:").and_then(NotificationType::from_name) {
+                let _ = moved_self.snooze_send.lock().unwrap().send(SnoozeMessage::ToggleNotificationType(notification_type));
+            }
+            let do_not_disturb = match id.strip_prefix("dnd:") {
+                Some("1h") => Some(DoNotDisturb::For(std::time::Duration::from_secs(60 * 60))),
+                Some("4h") => Some(DoNotDisturb::For(std::time::Duration::from_secs(4 * 60 * 60))),
+                Some("tomorrow") => Some(DoNotDisturb::UntilTomorrow),
+                Some("off") => Some(DoNotDisturb::Off),
+                _ => None,
+            };
+            if let Some(do_not_disturb) = do_not_disturb {
+                let _ = moved_self.snooze_send.lock().unwrap().send(SnoozeMessage::DoNotDisturb(do_not_disturb));
+            }
+            if let Some((account, rest)) = id.strip_prefix("repo:").and_then(|rest| rest.split_once(':')) {
                 if let Some((owner, rest)) = rest.split_once(':') {
                     if let Some((repo, rest)) = rest.split_once(":") {
                         if let Some((command, action)) = rest.split_once(":") {
                             if command == "snooze" {
-                                let mut until = DateTime::now_utc();
-
-                                if (action == "day") {
-                                    until = DateTime::now_utc().unwrap().add_days(1);
-                                }
-                                if (action == "week") {
-                                    until = DateTime::now_utc().unwrap().add_days(7);
-                                }
-                                if (action == "month") {
-                                    until = DateTime::now_utc().unwrap().add_days(30);
+                                match action.parse::<i32>() {
+                                    Ok(days) => {
+                                        let until = DateTime::now_utc().unwrap().add_days(days);
+                                        moved_self.clone().store.snooze_repo(account, owner, repo, until.unwrap());
+                                    }
+                                    Err(e) => eprintln!("Invalid snooze duration {}: {}", action, e),
                                 }
-                                moved_self.clone().store.snooze_repo(owner, repo, until.unwrap());
                             }
                             if command == "reason" {
-                                match moved_self.clone().store.toggle_reason(owner, repo, action) {
+                                match moved_self.clone().store.toggle_reason(account, owner, repo, action) {
                                     Ok(now_enabled) => {
                                         // reflect in UI
-                                        let item_id = format!("reason:{}:{}:{}", action, owner, repo);
+                                        let item_id = format!("reason:{}:{}:{}:{}", action, account, owner, repo);
                                         //let item = app.tray_handle().get_item(&item_id);
                                         // let _ = item.set_selected(now_enabled); // or set_checked() depending on API
-                                        println!("toggle_reason: {} for {}/{} id: {}", action, owner, repo, item_id);
+                                        println!("toggle_reason: {} for [{}] {}/{} id: {}", action, account, owner, repo, item_id);
                                     }
                                     Err(e) => {
                                         eprintln!("toggle_reason error: {e}");
@@ -313,7 +419,9 @@ impl Tray {
                 break;
             }
             self.process_menu_clicks();
-            self.process_messages();
+            if self.process_messages() {
+                menu_built.borrow().set_menu(Some(self.regenerate_menu()));
+            }
             gtk::main_iteration();
         }
 